@@ -0,0 +1,455 @@
+// 音频引擎 - 解码缓存 + 混音器，声部交给可替换的音频输出
+use log::{error, info, warn};
+use rodio::source::UniformSourceIterator;
+use rodio::{Decoder, Source};
+use crossbeam_channel::Receiver;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::synth::{self, SynthVoicing};
+use crate::trim::{self, TrimSettings, Trimmed};
+
+pub mod sink;
+
+mod aiff;
+mod polyphony;
+
+pub use polyphony::{PolyphonySettings, VoiceLimiter, VoiceStats};
+pub use sink::{output_device_names, AudioSink, CaptureSink, DeviceSink, NullSink};
+
+// 混音器输出格式，所有样本在加载时统一转换为该格式
+pub const OUTPUT_CHANNELS: u16 = 2;
+pub const OUTPUT_SAMPLE_RATE: u32 = 44_100;
+
+// 可以解码的音频文件扩展名（小写）
+pub const SUPPORTED_EXTENSIONS: &[&str] = &["wav", "mp3", "flac", "ogg", "oga", "aif", "aiff", "aifc"];
+// 常见但当前构建无法解码的格式，扫描时给出明确的原因而不是在按键时解码失败
+pub const UNSUPPORTED_EXTENSIONS: &[&str] = &["m4a", "aac", "opus", "wma"];
+
+// 等待混音的声部队列上限，避免按键风暴时无限积压
+const VOICE_QUEUE_CAPACITY: usize = 64;
+
+// 已解码的音频样本（交错排列的 f32，格式为 OUTPUT_CHANNELS / OUTPUT_SAMPLE_RATE）
+pub struct Sample {
+    pub data: Vec<f32>,
+}
+
+impl Sample {
+    pub fn frames(&self) -> usize {
+        self.data.len() / OUTPUT_CHANNELS as usize
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames() as f64 / OUTPUT_SAMPLE_RATE as f64)
+    }
+}
+
+// 样本引用：整个音频文件、音频精灵中的一段 (起始毫秒, 时长毫秒)，或内置合成器生成的样本
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SampleRef {
+    File { path: PathBuf, segment: Option<(u32, u32)> },
+    Synth(SynthVoicing),
+}
+
+impl SampleRef {
+    pub fn file(path: PathBuf) -> Self {
+        SampleRef::File { path, segment: None }
+    }
+
+    pub fn segment(path: PathBuf, start_ms: u32, duration_ms: u32) -> Self {
+        SampleRef::File { path, segment: Some((start_ms, duration_ms)) }
+    }
+}
+
+impl fmt::Display for SampleRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SampleRef::File { path, segment: None } => write!(f, "{}", path.display()),
+            SampleRef::File { path, segment: Some((start_ms, duration_ms)) } => {
+                write!(f, "{} [{} ms, {} ms]", path.display(), start_ms, duration_ms)
+            }
+            SampleRef::Synth(voicing) => write!(f, "合成音效 ({:.0} Hz, {:.0} ms)", voicing.pitch, voicing.decay_ms),
+        }
+    }
+}
+
+// 一次播放的参数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoiceParams {
+    pub gain: f32,
+    // 播放速率，1.0 为原速原调，2.0 高一个八度
+    pub rate: f32,
+    // 立体声声像，-1.0 为最左，0.0 居中，1.0 为最右
+    pub pan: f32,
+    // 声部组，同一个按键的声音属于同一组，用于同键重触发
+    pub group: Option<u64>,
+}
+
+impl VoiceParams {
+    pub fn new(gain: f32) -> Self {
+        VoiceParams { gain, rate: 1.0, pan: 0.0, group: None }
+    }
+
+    // 每个输出声道的增益。声像用平衡方式实现：偏向一侧时只衰减另一侧，居中时两侧都等于 gain
+    fn channel_gains(&self) -> [f32; OUTPUT_CHANNELS as usize] {
+        let pan = self.pan.clamp(-1.0, 1.0);
+        [self.gain * (1.0 - pan).min(1.0), self.gain * (1.0 + pan).min(1.0)]
+    }
+}
+
+// 正在播放的一个声部
+struct Voice {
+    sample: Arc<Sample>,
+    position: f64, // 以帧为单位，变调时带小数
+    params: VoiceParams,
+    channel_gains: [f32; OUTPUT_CHANNELS as usize],
+}
+
+impl Voice {
+    fn new(sample: Arc<Sample>, params: VoiceParams) -> Self {
+        let channel_gains = params.channel_gains();
+        Voice { sample, position: 0.0, params, channel_gains }
+    }
+
+    // 当前位置某个声道的值，两帧之间线性插值；原速播放时小数部分为 0，结果与原样本完全一致
+    fn value(&self, channel: usize) -> f32 {
+        let channels = OUTPUT_CHANNELS as usize;
+        let frame = self.position as usize;
+        let frac = (self.position - frame as f64) as f32;
+        let data = &self.sample.data;
+        let current = data.get(frame * channels + channel).copied().unwrap_or(0.0);
+        if frac == 0.0 {
+            return current;
+        }
+        let next = data.get((frame + 1) * channels + channel).copied().unwrap_or(0.0);
+        current + (next - current) * frac
+    }
+
+    fn is_finished(&self) -> bool {
+        self.position >= self.sample.frames() as f64
+    }
+}
+
+// 混音器：把所有重叠的按键音混合成一条输出流
+struct Mixer {
+    incoming: Receiver<Voice>,
+    voices: Vec<Voice>,
+    channel: u16,
+    limiter: Arc<VoiceLimiter>,
+}
+
+impl Mixer {
+    fn new(incoming: Receiver<Voice>, limiter: Arc<VoiceLimiter>) -> Self {
+        Mixer {
+            incoming,
+            voices: Vec::new(),
+            channel: 0,
+            limiter,
+        }
+    }
+
+    fn add_voice(&mut self, voice: Voice) {
+        self.limiter.admit(&mut self.voices, voice);
+    }
+}
+
+impl Iterator for Mixer {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        // 每帧开始时接收新声部，保证延迟不超过一个输出缓冲
+        if self.channel == 0 {
+            while let Ok(voice) = self.incoming.try_recv() {
+                self.add_voice(voice);
+            }
+        }
+
+        let channel = self.channel as usize;
+        let mut mixed = 0.0;
+        for voice in &self.voices {
+            mixed += voice.value(channel) * voice.channel_gains[channel];
+        }
+
+        self.channel = (self.channel + 1) % OUTPUT_CHANNELS;
+        if self.channel == 0 {
+            for voice in &mut self.voices {
+                voice.position += voice.params.rate as f64;
+            }
+            self.voices.retain(|voice| !voice.is_finished());
+        }
+
+        Some(mixed.clamp(-1.0, 1.0))
+    }
+}
+
+impl Source for Mixer {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        OUTPUT_CHANNELS
+    }
+
+    fn sample_rate(&self) -> u32 {
+        OUTPUT_SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+// 常驻音频引擎：样本解码后缓存在内存中，由音频输出负责播放
+pub struct AudioEngine {
+    sink: Box<dyn AudioSink>,
+    cache: Mutex<HashMap<SampleRef, Arc<Sample>>>,
+    sprites: Mutex<HashMap<PathBuf, Arc<Sample>>>, // 音频精灵的整个文件
+    trim: TrimSettings,
+    trims: Mutex<HashMap<SampleRef, Trimmed>>, // 每个样本裁掉的静音，用于诊断输出
+    limiter: Arc<VoiceLimiter>,
+}
+
+impl AudioEngine {
+    // 按环境变量 KEY_SOUND_AUDIO=device|null|capture 选择音频输出，默认使用声卡的 output_device 设备
+    // （None 为系统默认）；声卡暂时无法打开时（如无音频设备的 Linux 服务器）声卡输出先保持静音，
+    // 设备出现后自动开始播放。只有音频输出线程无法启动时才退回静音输出
+    pub fn new(output_device: Option<String>) -> Self {
+        let limiter = Arc::new(VoiceLimiter::default());
+        let sink: Box<dyn AudioSink> = match std::env::var("KEY_SOUND_AUDIO").as_deref() {
+            Ok("null") => Box::new(NullSink),
+            Ok("capture") => Box::new(CaptureSink::new()),
+            _ => match DeviceSink::open(output_device, Arc::clone(&limiter)) {
+                Ok(sink) => Box::new(sink),
+                Err(e) => {
+                    warn!("{}，改用静音输出", e);
+                    Box::new(NullSink)
+                }
+            },
+        };
+        Self::with_limiter(sink, limiter)
+    }
+
+    pub fn with_sink(sink: Box<dyn AudioSink>) -> Self {
+        Self::with_limiter(sink, Arc::new(VoiceLimiter::default()))
+    }
+
+    // 声卡输出的混音器与引擎共用同一个复音数限制器
+    fn with_limiter(sink: Box<dyn AudioSink>, limiter: Arc<VoiceLimiter>) -> Self {
+        info!("音频输出: {}", sink.name());
+        AudioEngine {
+            sink,
+            cache: Mutex::new(HashMap::new()),
+            sprites: Mutex::new(HashMap::new()),
+            trim: TrimSettings::default(),
+            trims: Mutex::new(HashMap::new()),
+            limiter,
+        }
+    }
+
+    // 设置复音数上限和抢占策略，立即对正在运行的混音器生效
+    pub fn with_polyphony(self, polyphony: PolyphonySettings) -> Self {
+        self.limiter.configure(polyphony);
+        self
+    }
+
+    // 到目前为止被抢占和丢弃的声部数
+    pub fn voice_stats(&self) -> VoiceStats {
+        self.limiter.stats()
+    }
+
+    // 设置样本加载时的静音裁剪，只影响之后加载的样本
+    pub fn with_trim(mut self, trim: TrimSettings) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    // 不打开声卡的引擎，只用于解码和缓存样本（离线渲染使用）
+    pub fn offline() -> Self {
+        Self::with_sink(Box::new(NullSink))
+    }
+
+    // 切换声卡输出设备，None 为系统默认设备
+    pub fn set_output_device(&self, device: Option<String>) {
+        self.sink.set_output_device(device);
+    }
+
+    // 预先解码样本，避免第一次按键时解码
+    pub fn preload(&self, sample_ref: &SampleRef) {
+        let _ = self.load(sample_ref);
+    }
+
+    pub fn play(&self, sample_ref: &SampleRef, params: VoiceParams) {
+        if let Some(sample) = self.load(sample_ref) {
+            self.sink.play(sample_ref, sample, params);
+        }
+    }
+
+    // 取得解码后的样本，首次访问时解码、裁剪静音并缓存
+    pub fn load(&self, sample_ref: &SampleRef) -> Option<Arc<Sample>> {
+        if let Some(sample) = self.cache.lock().unwrap().get(sample_ref) {
+            return Some(Arc::clone(sample));
+        }
+
+        let sample = match sample_ref {
+            // 音频精灵：整个文件只解码一次，各段从中切出后分别裁剪
+            SampleRef::File { path, segment: Some((start_ms, duration_ms)) } => {
+                let whole = self.load_sprite(path)?;
+                self.trim(sample_ref, slice_sample(&whole, *start_ms, *duration_ms))
+            }
+            SampleRef::File { path, segment: None } => match decode_file(path) {
+                Ok(sample) => {
+                    info!("已解码音频: {} ({:.0} ms)", path.display(), sample.duration().as_secs_f64() * 1000.0);
+                    self.trim(sample_ref, sample)
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    return None;
+                }
+            },
+            SampleRef::Synth(voicing) => {
+                let sample = synth::render(voicing);
+                info!("已生成{} ({:.0} ms)", sample_ref, sample.duration().as_secs_f64() * 1000.0);
+                sample
+            }
+        };
+
+        let sample = Arc::new(sample);
+        self.cache.lock().unwrap().insert(sample_ref.clone(), Arc::clone(&sample));
+        Some(sample)
+    }
+
+    // 音频精灵的整个文件，不裁剪，保证各段的偏移与文件一致
+    fn load_sprite(&self, path: &Path) -> Option<Arc<Sample>> {
+        if let Some(sample) = self.sprites.lock().unwrap().get(path) {
+            return Some(Arc::clone(sample));
+        }
+        let sample = match decode_file(path) {
+            Ok(sample) => Arc::new(sample),
+            Err(e) => {
+                error!("{}", e);
+                return None;
+            }
+        };
+        info!("已解码音频: {} ({:.0} ms)", path.display(), sample.duration().as_secs_f64() * 1000.0);
+        self.sprites.lock().unwrap().insert(path.to_path_buf(), Arc::clone(&sample));
+        Some(sample)
+    }
+
+    fn trim(&self, sample_ref: &SampleRef, sample: Sample) -> Sample {
+        if !self.trim.enabled {
+            return sample;
+        }
+        let (sample, trimmed) = trim::trim(&sample, &self.trim);
+        if !trimmed.is_empty() {
+            info!("已裁剪静音: {} 开头 {:.1} ms, 结尾 {:.1} ms",
+                  sample_ref, trimmed.leading.as_secs_f64() * 1000.0, trimmed.trailing.as_secs_f64() * 1000.0);
+        }
+        self.trims.lock().unwrap().insert(sample_ref.clone(), trimmed);
+        sample
+    }
+
+    // 样本加载时被裁掉的静音，未加载或未裁剪的样本返回None
+    pub fn trimmed(&self, sample_ref: &SampleRef) -> Option<Trimmed> {
+        self.trims.lock().unwrap().get(sample_ref).copied()
+    }
+
+    // 离线混音：每个声部从指定的帧开始播放，与实时输出使用同一个混音器和复音数限制，结果逐样本一致。
+    // 返回交错排列的 f32 样本，长度到最后一个声部播放完为止
+    pub fn render_offline(&self, mut schedule: Vec<(usize, Arc<Sample>, VoiceParams)>) -> Vec<f32> {
+        schedule.sort_by_key(|(start_frame, _, _)| *start_frame);
+        let mut pending = schedule.into_iter().peekable();
+        let mut mixer = Mixer::new(crossbeam_channel::never(), Arc::clone(&self.limiter));
+        let mut output = Vec::new();
+        let mut frame = 0;
+
+        while pending.peek().is_some() || !mixer.voices.is_empty() {
+            while let Some((_, sample, params)) = pending.next_if(|(start_frame, _, _)| *start_frame <= frame) {
+                mixer.add_voice(Voice::new(sample, params));
+            }
+            for _ in 0..OUTPUT_CHANNELS {
+                output.push(mixer.next().unwrap_or(0.0));
+            }
+            frame += 1;
+        }
+
+        output
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+fn is_aiff(path: &Path) -> bool {
+    matches!(extension(path).as_str(), "aif" | "aiff" | "aifc")
+}
+
+fn open_decoder(path: &Path) -> Result<Decoder<BufReader<File>>, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("无法打开音频文件 {}: {:?}", path.display(), e))?;
+    Decoder::new(BufReader::new(file))
+        .map_err(|e| format!("音频解码失败 {}: {:?}", path.display(), e))
+}
+
+fn decode_aiff(path: &Path) -> Result<rodio::buffer::SamplesBuffer<f32>, String> {
+    let data = std::fs::read(path)
+        .map_err(|e| format!("无法打开音频文件 {}: {:?}", path.display(), e))?;
+    aiff::decode(&data).map_err(|e| format!("音频解码失败 {}: {}", path.display(), e))
+}
+
+fn to_output<S>(source: S) -> Sample
+where
+    S: Source,
+    S::Item: rodio::Sample,
+    f32: rodio::cpal::FromSample<S::Item>,
+{
+    let uniform: UniformSourceIterator<S, f32> =
+        UniformSourceIterator::new(source, OUTPUT_CHANNELS, OUTPUT_SAMPLE_RATE);
+    Sample {
+        data: uniform.collect(),
+    }
+}
+
+// 解码整个音频文件并转换为混音器的输出格式
+pub fn decode_file(path: &Path) -> Result<Sample, String> {
+    if is_aiff(path) {
+        Ok(to_output(decode_aiff(path)?))
+    } else {
+        Ok(to_output(open_decoder(path)?))
+    }
+}
+
+// 扫描音频文件时探测能否解码：识别格式并解出第一个样本，不解码整个文件
+pub fn probe_file(path: &Path) -> Result<(), String> {
+    let ext = extension(path);
+    if UNSUPPORTED_EXTENSIONS.contains(&ext.as_str()) {
+        return Err(format!("当前版本不支持 {} 格式", ext));
+    }
+    let first = if is_aiff(path) {
+        decode_aiff(path)?.next()
+    } else {
+        open_decoder(path)?.next().map(f32::from)
+    };
+    match first {
+        Some(_) => Ok(()),
+        None => Err(format!("音频文件 {} 没有任何样本", path.display())),
+    }
+}
+
+// 按毫秒偏移从已解码样本中切出一段，越界部分自动截断
+fn slice_sample(sample: &Sample, start_ms: u32, duration_ms: u32) -> Sample {
+    let channels = OUTPUT_CHANNELS as u64;
+    let to_index = |ms: u32| (ms as u64 * OUTPUT_SAMPLE_RATE as u64 / 1000 * channels) as usize;
+    let start = to_index(start_ms).min(sample.data.len());
+    let end = to_index(start_ms.saturating_add(duration_ms)).min(sample.data.len());
+    Sample {
+        data: sample.data[start..end].to_vec(),
+    }
+}
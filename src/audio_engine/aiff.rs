@@ -0,0 +1,251 @@
+// AIFF / AIFF-C 解码 - rodio 不支持 AIFF，macOS 系统音效（如 Tink.aiff）使用该格式
+use rodio::buffer::SamplesBuffer;
+
+// 读取大端整数，越界时返回None
+fn be_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+// 80 位扩展精度浮点数，COMM 块中的采样率使用这种格式
+fn extended(data: &[u8], at: usize) -> Option<f64> {
+    let exponent = be_u16(data, at)?;
+    let mantissa = u64::from_be_bytes(data.get(at + 2..at + 10)?.try_into().ok()?);
+    if exponent & 0x7FFF == 0 && mantissa == 0 {
+        return Some(0.0);
+    }
+    let sign = if exponent & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let power = (exponent & 0x7FFF) as i32 - 16383 - 63;
+    Some(sign * mantissa as f64 * 2f64.powi(power))
+}
+
+// 样本数据的编码方式
+#[derive(Clone, Copy, PartialEq)]
+enum Encoding {
+    BigEndian, // AIFF 和 AIFF-C 的 NONE / twos
+    LittleEndian, // AIFF-C 的 sowt
+    Float32,
+    Float64,
+}
+
+struct Common {
+    channels: u16,
+    frames: u32,
+    bits: u16,
+    sample_rate: f64,
+    encoding: Encoding,
+}
+
+fn parse_common(chunk: &[u8], aifc: bool) -> Result<Common, String> {
+    let truncated = || "COMM 块不完整".to_string();
+    let encoding = if aifc {
+        match chunk.get(18..22).ok_or_else(truncated)? {
+            b"NONE" | b"twos" => Encoding::BigEndian,
+            b"sowt" => Encoding::LittleEndian,
+            b"fl32" | b"FL32" => Encoding::Float32,
+            b"fl64" | b"FL64" => Encoding::Float64,
+            other => return Err(format!("不支持的 AIFF-C 压缩格式 {}", String::from_utf8_lossy(other))),
+        }
+    } else {
+        Encoding::BigEndian
+    };
+    Ok(Common {
+        channels: be_u16(chunk, 0).ok_or_else(truncated)?,
+        frames: be_u32(chunk, 2).ok_or_else(truncated)?,
+        bits: be_u16(chunk, 6).ok_or_else(truncated)?,
+        sample_rate: extended(chunk, 8).ok_or_else(truncated)?,
+        encoding,
+    })
+}
+
+// 单个样本转换为 -1.0..1.0 的浮点数
+fn sample_value(bytes: &[u8], encoding: Encoding) -> f32 {
+    match encoding {
+        Encoding::Float32 => f32::from_be_bytes(bytes.try_into().unwrap()),
+        Encoding::Float64 => f64::from_be_bytes(bytes.try_into().unwrap()) as f32,
+        Encoding::BigEndian | Encoding::LittleEndian => {
+            // 整数样本左对齐到 32 位再缩放，8/16/24/32 位统一处理
+            let mut value: u32 = 0;
+            for (i, byte) in bytes.iter().enumerate() {
+                let index = if encoding == Encoding::BigEndian { i } else { bytes.len() - 1 - i };
+                value |= (*byte as u32) << (24 - 8 * index);
+            }
+            value as i32 as f32 / 2_147_483_648.0
+        }
+    }
+}
+
+// 解码整个 AIFF / AIFF-C 文件为交错排列的浮点样本，保留原始声道数和采样率
+pub fn decode(data: &[u8]) -> Result<SamplesBuffer<f32>, String> {
+    let aifc = match (data.get(0..4), data.get(8..12)) {
+        (Some(b"FORM"), Some(b"AIFF")) => false,
+        (Some(b"FORM"), Some(b"AIFC")) => true,
+        _ => return Err("不是 AIFF 文件".to_string()),
+    };
+
+    let mut common = None;
+    let mut sound = None;
+    let mut at = 12;
+    while let (Some(id), Some(size)) = (data.get(at..at + 4), be_u32(data, at + 4)) {
+        let start = at + 8;
+        let end = start.saturating_add(size as usize).min(data.len());
+        let chunk = &data[start..end];
+        match id {
+            b"COMM" => common = Some(parse_common(chunk, aifc)?),
+            // SSND 块以 offset 和 blockSize 开头，样本从 8 + offset 处开始
+            b"SSND" => {
+                let offset = be_u32(chunk, 0).ok_or("SSND 块不完整")? as usize;
+                sound = Some(chunk.get(8 + offset..).ok_or("SSND 块不完整")?);
+            }
+            _ => {}
+        }
+        // 块长度为奇数时有一个填充字节
+        at = start.saturating_add(size as usize + (size as usize & 1));
+    }
+
+    let common = common.ok_or("缺少 COMM 块")?;
+    let sound = sound.ok_or("缺少 SSND 块")?;
+    let bytes = match common.encoding {
+        Encoding::Float32 => 4,
+        Encoding::Float64 => 8,
+        _ if (1..=32).contains(&common.bits) => (common.bits as usize).div_ceil(8),
+        _ => return Err(format!("不支持的样本位数 {}", common.bits)),
+    };
+    let sample_rate = common.sample_rate.round();
+    if common.channels == 0 || !(1.0..=u32::MAX as f64).contains(&sample_rate) {
+        return Err(format!("无效的格式: {} 声道, {} Hz", common.channels, common.sample_rate));
+    }
+
+    let count = (common.frames as usize * common.channels as usize).min(sound.len() / bytes);
+    let samples: Vec<f32> = sound.chunks_exact(bytes)
+        .take(count)
+        .map(|bytes| sample_value(bytes, common.encoding))
+        .collect();
+    Ok(SamplesBuffer::new(common.channels, sample_rate as u32, samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::Source;
+
+    // 整数采样率的 80 位扩展精度表示
+    fn extended_rate(rate: u32) -> [u8; 10] {
+        let exponent = 31 - rate.leading_zeros();
+        let mut bytes = [0; 10];
+        bytes[..2].copy_from_slice(&(16383 + exponent as u16).to_be_bytes());
+        bytes[2..].copy_from_slice(&((rate as u64) << (63 - exponent)).to_be_bytes());
+        bytes
+    }
+
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((body.len() as u32).to_be_bytes());
+        chunk.extend(body);
+        if body.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn comm(channels: u16, frames: u32, bits: u16, rate: u32, compression: Option<&[u8; 4]>) -> Vec<u8> {
+        let mut body = channels.to_be_bytes().to_vec();
+        body.extend(frames.to_be_bytes());
+        body.extend(bits.to_be_bytes());
+        body.extend(extended_rate(rate));
+        if let Some(compression) = compression {
+            body.extend(compression);
+            body.push(0); // 空的压缩格式名称（pstring）
+            body.push(0);
+        }
+        chunk(b"COMM", &body)
+    }
+
+    fn ssnd(samples: &[u8]) -> Vec<u8> {
+        let mut body = vec![0; 8]; // offset 和 blockSize
+        body.extend(samples);
+        chunk(b"SSND", &body)
+    }
+
+    fn form(kind: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = kind.iter().copied().chain(chunks.concat()).collect();
+        let mut data = b"FORM".to_vec();
+        data.extend((body.len() as u32).to_be_bytes());
+        data.extend(body);
+        data
+    }
+
+    fn decoded(data: &[u8]) -> (u16, u32, Vec<f32>) {
+        let buffer = decode(data).unwrap();
+        (buffer.channels(), buffer.sample_rate(), buffer.collect())
+    }
+
+    #[test]
+    fn decodes_big_endian_16_bit() {
+        let samples: Vec<u8> = [0x4000i16, -0x4000, 0x7FFF, i16::MIN].iter().flat_map(|value| value.to_be_bytes()).collect();
+        let data = form(b"AIFF", &[comm(2, 2, 16, 44_100, None), ssnd(&samples)]);
+        let (channels, rate, values) = decoded(&data);
+        assert_eq!((channels, rate), (2, 44_100));
+        assert_eq!(values[..2], [0.5, -0.5]);
+        assert!((values[2] - 1.0).abs() < 1e-4);
+        assert_eq!(values[3], -1.0);
+    }
+
+    #[test]
+    fn decodes_aifc_sowt() {
+        let samples: Vec<u8> = [0x4000i16, -0x2000].iter().flat_map(|value| value.to_le_bytes()).collect();
+        let data = form(b"AIFC", &[comm(1, 2, 16, 22_050, Some(b"sowt")), ssnd(&samples)]);
+        assert_eq!(decoded(&data), (1, 22_050, vec![0.5, -0.25]));
+    }
+
+    #[test]
+    fn decodes_aifc_fl32() {
+        let samples: Vec<u8> = [0.25f32, -0.75].iter().flat_map(|value| value.to_be_bytes()).collect();
+        let data = form(b"AIFC", &[comm(1, 2, 32, 48_000, Some(b"fl32")), ssnd(&samples)]);
+        assert_eq!(decoded(&data), (1, 48_000, vec![0.25, -0.75]));
+    }
+
+    #[test]
+    fn skips_padding_after_odd_length_chunks() {
+        // 奇数长度的未知块后有一个填充字节，之后的块仍然要能找到
+        let data = form(b"AIFF", &[chunk(b"NAME", b"Tink"), chunk(b"ANNO", b"odd"), comm(1, 1, 8, 8_000, None), ssnd(&[0x40])]);
+        assert_eq!(decoded(&data), (1, 8_000, vec![0.5]));
+    }
+
+    #[test]
+    fn truncated_sound_data_decodes_what_is_there() {
+        // COMM 声明 4 帧但 SSND 只有 2.5 帧，只解码完整的样本
+        let mut data = form(b"AIFF", &[comm(1, 4, 16, 44_100, None), ssnd(&[0x40, 0x00, 0xC0, 0x00, 0x20])]);
+        let (_, _, values) = decoded(&data);
+        assert_eq!(values, vec![0.5, -0.5]);
+
+        // 文件在 SSND 的 blockSize 字段中间被截断
+        data.truncate(data.len() - 8);
+        assert_eq!(decode(&data).err().unwrap(), "SSND 块不完整");
+    }
+
+    #[test]
+    fn missing_chunks_are_errors() {
+        let data = form(b"AIFF", &[ssnd(&[0, 0])]);
+        assert_eq!(decode(&data).err().unwrap(), "缺少 COMM 块");
+        let data = form(b"AIFF", &[comm(1, 1, 16, 44_100, None)]);
+        assert_eq!(decode(&data).err().unwrap(), "缺少 SSND 块");
+        assert!(decode(b"RIFF\0\0\0\0WAVE").is_err());
+    }
+
+    #[test]
+    fn rejects_unsupported_compression() {
+        let data = form(b"AIFC", &[comm(1, 1, 16, 44_100, Some(b"ima4")), ssnd(&[0, 0])]);
+        assert!(decode(&data).err().unwrap().contains("ima4"));
+    }
+
+    #[test]
+    fn reads_extended_sample_rates() {
+        for rate in [8_000, 22_050, 44_100, 48_000, 96_000] {
+            assert_eq!(extended(&extended_rate(rate), 0), Some(rate as f64));
+        }
+    }
+}
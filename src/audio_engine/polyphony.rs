@@ -0,0 +1,238 @@
+// 复音数限制 - 同时播放的声部超过上限时按策略抢占旧声部，快速打字或乱按时声部不会无限堆积
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+use super::{Voice, OUTPUT_CHANNELS, OUTPUT_SAMPLE_RATE};
+
+// 估计声部当前响度时分析的长度
+const LEVEL_WINDOW_MS: u32 = 10;
+
+// 声部已满时选择被抢占声部的策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VoiceStealing {
+    // 停止最早开始的声部
+    #[default]
+    Oldest,
+    // 停止当前最轻的声部
+    Quietest,
+    // 同一个按键再次发声时停止它上一次的声音；已满且没有同键声部时丢弃新声部
+    SameKey,
+}
+
+impl VoiceStealing {
+    const ALL: [VoiceStealing; 3] = [VoiceStealing::Oldest, VoiceStealing::Quietest, VoiceStealing::SameKey];
+
+    fn from_index(index: u8) -> Self {
+        Self::ALL.get(index as usize).copied().unwrap_or_default()
+    }
+}
+
+// 复音数设置
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct PolyphonySettings {
+    pub max_voices: usize, // 同时播放的声部上限，0 为不限制
+    pub stealing: VoiceStealing,
+}
+
+impl Default for PolyphonySettings {
+    fn default() -> Self {
+        PolyphonySettings {
+            max_voices: 16,
+            stealing: VoiceStealing::Oldest,
+        }
+    }
+}
+
+// 复音数限制器，由引擎和各个混音器共享；混音器在音频回调中收到新声部时访问，
+// 设置保存在原子变量中，不加锁
+pub struct VoiceLimiter {
+    max_voices: AtomicUsize,
+    stealing: AtomicU8,
+    stolen: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Default for VoiceLimiter {
+    fn default() -> Self {
+        let settings = PolyphonySettings::default();
+        VoiceLimiter {
+            max_voices: AtomicUsize::new(settings.max_voices),
+            stealing: AtomicU8::new(settings.stealing as u8),
+            stolen: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+}
+
+// 被抢占和被丢弃的声部数，用于诊断
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VoiceStats {
+    pub stolen: u64,
+    pub dropped: u64,
+}
+
+impl VoiceLimiter {
+    pub fn configure(&self, settings: PolyphonySettings) {
+        self.max_voices.store(settings.max_voices, Ordering::Relaxed);
+        self.stealing.store(settings.stealing as u8, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> VoiceStats {
+        VoiceStats {
+            stolen: self.stolen.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    // 声部队列已满等原因在进入混音器之前就被丢弃
+    pub fn count_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // 把新声部加入正在播放的声部，必要时按策略抢占
+    pub(super) fn admit(&self, voices: &mut Vec<Voice>, voice: Voice) {
+        let max_voices = self.max_voices.load(Ordering::Relaxed);
+        let stealing = VoiceStealing::from_index(self.stealing.load(Ordering::Relaxed));
+        if stealing == VoiceStealing::SameKey {
+            if let Some(group) = voice.params.group {
+                let before = voices.len();
+                voices.retain(|playing| playing.params.group != Some(group));
+                self.steal(before - voices.len());
+            }
+        }
+
+        if max_voices > 0 && voices.len() >= max_voices {
+            let excess = voices.len() + 1 - max_voices;
+            match stealing {
+                VoiceStealing::Oldest => {
+                    // 新声部总是追加在末尾，最前面的就是最早开始的
+                    voices.drain(..excess);
+                    self.steal(excess);
+                }
+                VoiceStealing::Quietest => {
+                    for _ in 0..excess {
+                        let quietest = voices.iter()
+                            .enumerate()
+                            .min_by(|(_, a), (_, b)| level(a).total_cmp(&level(b)))
+                            .map(|(index, _)| index);
+                        if let Some(index) = quietest {
+                            voices.remove(index);
+                            self.steal(1);
+                        }
+                    }
+                }
+                VoiceStealing::SameKey => {
+                    self.count_dropped();
+                    debug!("声部已满（{} 个），丢弃新声部", max_voices);
+                    return;
+                }
+            }
+        }
+        voices.push(voice);
+    }
+
+    fn steal(&self, count: usize) {
+        if count > 0 {
+            self.stolen.fetch_add(count as u64, Ordering::Relaxed);
+            debug!("抢占 {} 个声部", count);
+        }
+    }
+}
+
+// 声部从当前位置开始一小段的均方根电平乘以音量，作为当前响度的估计
+fn level(voice: &Voice) -> f32 {
+    let channels = OUTPUT_CHANNELS as usize;
+    let start = voice.position as usize * channels;
+    let window = (OUTPUT_SAMPLE_RATE * LEVEL_WINDOW_MS / 1000) as usize * channels;
+    let data = &voice.sample.data;
+    let slice = &data[start.min(data.len())..(start + window).min(data.len())];
+    if slice.is_empty() {
+        return 0.0;
+    }
+    let power = slice.iter().map(|value| value * value).sum::<f32>() / slice.len() as f32;
+    power.sqrt() * voice.params.gain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_engine::{Sample, VoiceParams};
+    use std::sync::Arc;
+
+    // 电平为 level 的声部，group 用于区分声部
+    fn voice(level: f32, group: u64) -> Voice {
+        let sample = Arc::new(Sample { data: vec![level; 4410 * OUTPUT_CHANNELS as usize] });
+        let mut params = VoiceParams::new(1.0);
+        params.group = Some(group);
+        Voice::new(sample, params)
+    }
+
+    fn groups(voices: &[Voice]) -> Vec<u64> {
+        voices.iter().filter_map(|voice| voice.params.group).collect()
+    }
+
+    fn limiter(max_voices: usize, stealing: VoiceStealing) -> VoiceLimiter {
+        let limiter = VoiceLimiter::default();
+        limiter.configure(PolyphonySettings { max_voices, stealing });
+        limiter
+    }
+
+    #[test]
+    fn stealing_round_trips_through_index() {
+        for stealing in VoiceStealing::ALL {
+            assert_eq!(VoiceStealing::from_index(stealing as u8), stealing);
+        }
+    }
+
+    #[test]
+    fn oldest_voice_is_stolen() {
+        let limiter = limiter(2, VoiceStealing::Oldest);
+        let mut voices = Vec::new();
+        for group in 1..=3 {
+            limiter.admit(&mut voices, voice(0.5, group));
+        }
+        assert_eq!(groups(&voices), vec![2, 3]);
+        assert_eq!(limiter.stats(), VoiceStats { stolen: 1, dropped: 0 });
+    }
+
+    #[test]
+    fn quietest_voice_is_stolen() {
+        let limiter = limiter(2, VoiceStealing::Quietest);
+        let mut voices = Vec::new();
+        limiter.admit(&mut voices, voice(0.5, 1));
+        limiter.admit(&mut voices, voice(0.1, 2));
+        limiter.admit(&mut voices, voice(0.3, 3));
+        assert_eq!(groups(&voices), vec![1, 3]);
+    }
+
+    #[test]
+    fn same_key_replaces_its_previous_voice() {
+        let limiter = limiter(2, VoiceStealing::SameKey);
+        let mut voices = Vec::new();
+        limiter.admit(&mut voices, voice(0.5, 1));
+        limiter.admit(&mut voices, voice(0.5, 2));
+        limiter.admit(&mut voices, voice(0.5, 1));
+        assert_eq!(groups(&voices), vec![2, 1]);
+        // 已满且没有同键声部时丢弃新声部
+        limiter.admit(&mut voices, voice(0.5, 3));
+        assert_eq!(groups(&voices), vec![2, 1]);
+        assert_eq!(limiter.stats(), VoiceStats { stolen: 1, dropped: 1 });
+    }
+
+    #[test]
+    fn reconfiguring_takes_effect_on_next_voice() {
+        let limiter = limiter(0, VoiceStealing::Oldest);
+        let mut voices = Vec::new();
+        for group in 1..=4 {
+            limiter.admit(&mut voices, voice(0.5, group));
+        }
+        assert_eq!(voices.len(), 4);
+        limiter.configure(PolyphonySettings { max_voices: 2, stealing: VoiceStealing::Oldest });
+        limiter.admit(&mut voices, voice(0.5, 5));
+        assert_eq!(groups(&voices), vec![4, 5]);
+    }
+}
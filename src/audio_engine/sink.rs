@@ -0,0 +1,330 @@
+// 音频输出 - 声卡输出、静音输出和记录输出，引擎只通过 AudioSink 提交声部
+use log::{debug, error, info, warn};
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::{Device, OutputStream};
+use crossbeam_channel::{bounded, unbounded, Receiver, RecvTimeoutError, Sender, TrySendError};
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{Mixer, Sample, SampleRef, Voice, VoiceLimiter, VoiceParams, VOICE_QUEUE_CAPACITY};
+
+// 音频输出：接收已解码的样本并负责播放（或丢弃、记录）
+pub trait AudioSink: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn play(&self, sample_ref: &SampleRef, sample: Arc<Sample>, params: VoiceParams);
+
+    // 切换输出设备，None 为系统默认设备；不对应声卡的输出忽略
+    fn set_output_device(&self, _device: Option<String>) {}
+}
+
+// 检查首选输出设备是否断开或重新出现的间隔
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// 当前系统中所有输出设备的名称
+pub fn output_device_names() -> Vec<String> {
+    match rodio::cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(e) => {
+            warn!("枚举音频输出设备失败: {:?}", e);
+            Vec::new()
+        }
+    }
+}
+
+fn find_output_device(name: &str) -> Option<Device> {
+    rodio::cpal::default_host()
+        .output_devices()
+        .ok()?
+        .find(|device| device.name().map(|n| n == name).unwrap_or(false))
+}
+
+// 声卡输出：常驻输出流 + 混音器
+pub struct DeviceSink {
+    voices: Sender<Voice>,
+    control: Sender<Option<String>>,
+    limiter: Arc<VoiceLimiter>,
+    // 输出流当前是否打开，未打开时不提交声部，避免声部在队列中积压到设备出现时一起播放
+    available: Arc<AtomicBool>,
+}
+
+impl DeviceSink {
+    // 在专用线程打开输出设备（None 或设备不存在时使用默认设备），等待第一次打开的结果后返回。
+    // 没有音频设备时（如 Linux 服务器启动时还没有声卡）照常返回，输出线程之后每个检查周期重试，
+    // 只有输出线程无法启动时返回错误
+    pub fn open(device: Option<String>, limiter: Arc<VoiceLimiter>) -> Result<Self, String> {
+        let (voices, incoming) = bounded(VOICE_QUEUE_CAPACITY);
+        let (control, control_rx) = unbounded();
+        let (ready_tx, ready_rx) = bounded(1);
+        let available = Arc::new(AtomicBool::new(false));
+
+        // OutputStream 不能跨线程传递，由专用线程持有并保持存活
+        let output_limiter = Arc::clone(&limiter);
+        let output_available = Arc::clone(&available);
+        thread::Builder::new()
+            .name("audio-output".to_string())
+            .spawn(move || run_output(incoming, output_limiter, output_available, device, control_rx, ready_tx))
+            .map_err(|e| format!("音频输出线程启动失败: {:?}", e))?;
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("{}，暂时静音，稍后重试打开声卡", e),
+            Err(_) => return Err("音频输出线程意外退出".to_string()),
+        }
+        Ok(DeviceSink { voices, control, limiter, available })
+    }
+}
+
+impl AudioSink for DeviceSink {
+    fn name(&self) -> &str {
+        "声卡输出"
+    }
+
+    fn play(&self, sample_ref: &SampleRef, sample: Arc<Sample>, params: VoiceParams) {
+        if !self.available.load(Ordering::Relaxed) {
+            debug!("音频输出流未打开，跳过播放: {:?}", sample_ref);
+            return;
+        }
+        match self.voices.try_send(Voice::new(sample, params)) {
+            Ok(()) => debug!("已提交音效: {:?}, 音量: {:.0}%, 速率: {:.3}", sample_ref, params.gain * 100.0, params.rate),
+            Err(TrySendError::Full(_)) => {
+                self.limiter.count_dropped();
+                warn!("声部队列已满，丢弃本次音效");
+            }
+            Err(TrySendError::Disconnected(_)) => debug!("音频输出不可用，跳过播放"),
+        }
+    }
+
+    fn set_output_device(&self, device: Option<String>) {
+        let _ = self.control.send(device);
+    }
+}
+
+// 打开的输出流，device 为实际使用的首选设备名，使用默认设备时为 None
+struct OpenStream {
+    _stream: OutputStream,
+    device: Option<String>,
+}
+
+// 优先打开首选设备，不存在或打不开时使用默认设备。
+// 每次打开都新建一个混音器，从同一个声部队列接收声部，切换前未播完的声部会被丢弃
+fn open_stream(preferred: Option<&str>, incoming: &Receiver<Voice>, limiter: &Arc<VoiceLimiter>) -> Result<OpenStream, String> {
+    if let Some(name) = preferred {
+        match find_output_device(name).map(|device| OutputStream::try_from_device(&device)) {
+            Some(Ok((stream, stream_handle))) => {
+                stream_handle.play_raw(Mixer::new(incoming.clone(), Arc::clone(limiter)))
+                    .map_err(|e| format!("启动混音器失败: {:?}", e))?;
+                info!("音频输出流已打开: {}", name);
+                return Ok(OpenStream { _stream: stream, device: Some(name.to_string()) });
+            }
+            Some(Err(e)) => warn!("无法打开输出设备 {}: {:?}，使用默认设备", name, e),
+            None => warn!("输出设备 {} 不存在，使用默认设备", name),
+        }
+    }
+
+    let (stream, stream_handle) = OutputStream::try_default()
+        .map_err(|e| format!("创建音频输出流失败: {:?}", e))?;
+    stream_handle.play_raw(Mixer::new(incoming.clone(), Arc::clone(limiter)))
+        .map_err(|e| format!("启动混音器失败: {:?}", e))?;
+    info!("音频输出流已打开: 默认设备");
+    Ok(OpenStream { _stream: stream, device: None })
+}
+
+fn run_output(incoming: Receiver<Voice>, limiter: Arc<VoiceLimiter>, available: Arc<AtomicBool>, mut preferred: Option<String>, control: Receiver<Option<String>>, ready: Sender<Result<(), String>>) {
+    // 上一次打开失败的错误信息
+    let mut last_error = None;
+    // 当前打开的输出流；打开失败时为 None，之后每个检查周期重试一次
+    let mut current = match open_stream(preferred.as_deref(), &incoming, &limiter) {
+        Ok(stream) => {
+            let _ = ready.send(Ok(()));
+            Some(stream)
+        }
+        Err(e) => {
+            let _ = ready.send(Err(e.clone()));
+            last_error = Some(e);
+            None
+        }
+    };
+    available.store(current.is_some(), Ordering::Relaxed);
+    // 上一次检查时首选设备是否存在，设备存在但打不开时不会反复重试
+    let mut last_present = current.as_ref().is_some_and(|stream| stream.device.is_some());
+
+    // 保持输出流存活，处理切换请求，并在首选设备断开时退回默认设备、重新出现时切回
+    loop {
+        let reopen = match control.recv_timeout(DEVICE_POLL_INTERVAL) {
+            Ok(device) => {
+                info!("切换输出设备: {}", device.as_deref().unwrap_or("系统默认"));
+                preferred = device;
+                true
+            }
+            Err(RecvTimeoutError::Timeout) => match (&current, &preferred) {
+                (None, _) => true,
+                (Some(stream), Some(name)) => {
+                    let present = output_device_names().contains(name);
+                    let was_present = std::mem::replace(&mut last_present, present);
+                    match (&stream.device, present) {
+                        (Some(_), false) => {
+                            warn!("输出设备 {} 已断开，改用默认设备", name);
+                            true
+                        }
+                        (None, true) if !was_present => {
+                            info!("输出设备 {} 已重新连接，切换回该设备", name);
+                            true
+                        }
+                        _ => false,
+                    }
+                }
+                (Some(_), None) => false,
+            },
+            // 引擎已释放，输出流随线程结束关闭
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        if !reopen {
+            continue;
+        }
+
+        // 先关闭旧的输出流，部分平台不允许同一设备同时打开两个流
+        current = None;
+        available.store(false, Ordering::Relaxed);
+        // 丢弃旧混音器没来得及取走的声部，新输出流不播放过时的按键声
+        let stale = incoming.try_iter().count();
+        if stale > 0 {
+            debug!("丢弃 {} 个未播放的声部", stale);
+        }
+        match open_stream(preferred.as_deref(), &incoming, &limiter) {
+            Ok(stream) => {
+                last_error = None;
+                current = Some(stream);
+            }
+            // 没有声卡时每个检查周期都会失败，同样的错误只记录一次
+            Err(e) if last_error.as_ref() == Some(&e) => debug!("{}，稍后重试", e),
+            Err(e) => {
+                error!("{}，稍后重试", e);
+                last_error = Some(e);
+            }
+        }
+        available.store(current.is_some(), Ordering::Relaxed);
+    }
+}
+
+// 静音输出：丢弃所有声部，用于没有音频设备的机器和离线渲染
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn name(&self) -> &str {
+        "静音输出"
+    }
+
+    fn play(&self, sample_ref: &SampleRef, _sample: Arc<Sample>, params: VoiceParams) {
+        debug!("静音输出丢弃音效: {:?}, 音量: {:.0}%", sample_ref, params.gain * 100.0);
+    }
+}
+
+// 记录输出收到的一个声部
+#[derive(Debug, Clone)]
+pub struct CapturedVoice {
+    pub sample_ref: SampleRef,
+    // 距记录输出创建的时间
+    pub at: Duration,
+    pub params: VoiceParams,
+    pub duration: Duration,
+}
+
+// 记录输出最多保留的声部数，实时监听时长时间运行也不会无限占用内存
+const CAPTURE_CAPACITY: usize = 4096;
+
+// 记录输出：不发声，记下本应播放的样本、时间和音量。克隆的句柄共享同一份记录，
+// 一份交给引擎，另一份留给调用方查看；超过容量时丢弃最早的记录
+#[derive(Clone)]
+pub struct CaptureSink {
+    start: Instant,
+    capacity: usize,
+    voices: Arc<Mutex<VecDeque<CapturedVoice>>>,
+}
+
+impl CaptureSink {
+    pub fn new() -> Self {
+        Self::with_capacity(CAPTURE_CAPACITY)
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        CaptureSink {
+            start: Instant::now(),
+            capacity: capacity.max(1),
+            voices: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    // 按播放顺序返回保留的记录
+    #[cfg(test)]
+    pub fn voices(&self) -> Vec<CapturedVoice> {
+        self.voices.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+impl AudioSink for CaptureSink {
+    fn name(&self) -> &str {
+        "记录输出"
+    }
+
+    fn play(&self, sample_ref: &SampleRef, sample: Arc<Sample>, params: VoiceParams) {
+        let captured = CapturedVoice {
+            sample_ref: sample_ref.clone(),
+            at: self.start.elapsed(),
+            params,
+            duration: sample.duration(),
+        };
+        info!("记录音效: {} @ {:.1} ms, 音量: {:.0}%, 速率: {:.3}, 声像: {:.2}, 时长: {:.0} ms",
+              captured.sample_ref, captured.at.as_secs_f64() * 1000.0,
+              captured.params.gain * 100.0, captured.params.rate, captured.params.pan, captured.duration.as_secs_f64() * 1000.0);
+        let mut voices = self.voices.lock().unwrap();
+        if voices.len() == self.capacity {
+            voices.pop_front();
+        }
+        voices.push_back(captured);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn sample(frames: usize) -> Arc<Sample> {
+        Arc::new(Sample { data: vec![0.0; frames * super::super::OUTPUT_CHANNELS as usize] })
+    }
+
+    fn play(sink: &CaptureSink, name: &str, gain: f32) {
+        sink.play(&SampleRef::file(PathBuf::from(name)), sample(441), VoiceParams::new(gain));
+    }
+
+    #[test]
+    fn capture_records_voices_in_order() {
+        let sink = CaptureSink::new();
+        let engine_side = sink.clone();
+        play(&engine_side, "a.wav", 0.5);
+        play(&engine_side, "b.wav", 0.25);
+
+        let voices = sink.voices();
+        assert_eq!(voices.len(), 2);
+        assert_eq!(voices[0].sample_ref, SampleRef::file(PathBuf::from("a.wav")));
+        assert_eq!(voices[0].params.gain, 0.5);
+        assert_eq!(voices[1].sample_ref, SampleRef::file(PathBuf::from("b.wav")));
+        assert_eq!(voices[1].duration, Duration::from_millis(10));
+        assert!(voices[0].at <= voices[1].at);
+    }
+
+    #[test]
+    fn capture_keeps_only_the_newest_voices() {
+        let sink = CaptureSink::with_capacity(2);
+        for name in ["a.wav", "b.wav", "c.wav"] {
+            play(&sink, name, 1.0);
+        }
+        let names: Vec<_> = sink.voices().into_iter().map(|voice| voice.sample_ref.to_string()).collect();
+        assert_eq!(names, vec!["b.wav", "c.wav"]);
+    }
+}
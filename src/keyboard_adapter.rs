@@ -1,15 +1,58 @@
-// 键盘事件适配层 - 使用CGEventTap实现键盘监听
-use log::{error, info};
-use core_graphics::event::{CGEvent, CGEventTap, CGEventTapLocation, CGEventTapOptions, CGEventTapPlacement, CGEventType, CGEventTapProxy, CGEventField};
-use core_foundation::runloop::{CFRunLoop, kCFRunLoopCommonModes, CFRunLoopRun};
+// 键盘事件适配层 - macOS 使用CGEventTap，Linux 读取evdev设备或使用X11 XRecord（同时上报鼠标按钮和滚轮）
+use serde::{Deserialize, Serialize};
+
+use std::time::Instant;
+
+mod listener;
+// 按键在实体键盘上的位置
+mod layout;
+#[cfg(target_os = "macos")]
+mod macos;
+// 修饰键相关的工具
+mod modifiers;
+#[cfg(target_os = "linux")]
+mod evdev;
+#[cfg(target_os = "linux")]
+mod x11;
+// 按预设脚本回放事件的输入源，不需要任何权限
+mod scripted;
+
+pub use layout::Layout;
+pub use listener::{EventSink, ListenerHandle, StopSignal};
+pub use modifiers::Modifiers;
+pub use scripted::ScriptedSource;
 
 // 键盘事件类型定义
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "key")]
 pub enum EventType {
     KeyPress(Key),
+    KeyRelease(Key),
+    ButtonPress(Button),
+    ButtonRelease(Button),
+    // 滚轮滚动的格数：delta_y 向上为正，delta_x 向右为正
+    Wheel { delta_x: i64, delta_y: i64 },
 }
 
-#[derive(Debug, Clone)]
+// 鼠标按钮
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Button {
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+    Unknown(u32),
+}
+
+impl Button {
+    // 按钮名称（与设置文件中的键名一致，如 "Left"、"Right"）
+    pub fn name(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Key {
     Alt,
     AltGr,
@@ -22,6 +65,7 @@ pub enum Key {
     End,
     Escape,
     F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    F13, F14, F15, F16, F17, F18, F19, F20,
     Home,
     LeftArrow,
     MetaLeft,
@@ -51,6 +95,10 @@ pub enum Key {
     Quote,
     BackSlash,
     IntlBackslash,
+    IntlYen, // JIS ¥ 键
+    IntlRo,  // JIS ろ 键（右Shift左侧）
+    Lang1,   // JIS かな 键
+    Lang2,   // JIS 英数 键
     KeyZ, KeyX, KeyC, KeyV, KeyB, KeyN, KeyM,
     Comma,
     Dot,
@@ -63,169 +111,497 @@ pub enum Key {
     KpDivide,
     Kp0, Kp1, Kp2, Kp3, Kp4, Kp5, Kp6, Kp7, Kp8, Kp9,
     KpDelete,
+    KpEqual,
+    KpComma,
     Function,
     Unknown(u32),
 }
 
+// 按键类别，用于给一类按键配置同一个音效
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyClass {
+    Letter,
+    Space,
+    Enter,
+    Backspace,
+    Modifier,
+    Navigation,
+    Function,
+    Other,
+}
+
+impl Key {
+    // 按键名称（与设置文件中的键名一致，如 "Space"、"KeyA"）
+    pub fn name(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    pub fn class(&self) -> KeyClass {
+        match self {
+            Key::KeyA | Key::KeyB | Key::KeyC | Key::KeyD | Key::KeyE | Key::KeyF | Key::KeyG
+            | Key::KeyH | Key::KeyI | Key::KeyJ | Key::KeyK | Key::KeyL | Key::KeyM | Key::KeyN
+            | Key::KeyO | Key::KeyP | Key::KeyQ | Key::KeyR | Key::KeyS | Key::KeyT | Key::KeyU
+            | Key::KeyV | Key::KeyW | Key::KeyX | Key::KeyY | Key::KeyZ => KeyClass::Letter,
+            Key::Space => KeyClass::Space,
+            Key::Return | Key::KpReturn => KeyClass::Enter,
+            Key::Backspace => KeyClass::Backspace,
+            Key::Alt | Key::AltGr | Key::ControlLeft | Key::ControlRight | Key::MetaLeft
+            | Key::MetaRight | Key::ShiftLeft | Key::ShiftRight | Key::CapsLock
+            | Key::Function => KeyClass::Modifier,
+            Key::UpArrow | Key::DownArrow | Key::LeftArrow | Key::RightArrow | Key::Home
+            | Key::End | Key::PageUp | Key::PageDown | Key::Insert | Key::Delete => KeyClass::Navigation,
+            Key::F1 | Key::F2 | Key::F3 | Key::F4 | Key::F5 | Key::F6 | Key::F7 | Key::F8
+            | Key::F9 | Key::F10 | Key::F11 | Key::F12 | Key::F13 | Key::F14 | Key::F15
+            | Key::F16 | Key::F17 | Key::F18 | Key::F19 | Key::F20 => KeyClass::Function,
+            _ => KeyClass::Other,
+        }
+    }
+}
+
+// 键盘事件。所有字段都不占堆内存，克隆开销很小
 #[derive(Debug, Clone)]
 pub struct Event {
     pub event_type: EventType,
+    // 事件被后端接收时的单调时钟时间
+    pub time: Instant,
+    // 是否为按住按键时系统产生的自动重复
+    pub is_repeat: bool,
+    // 事件发生后生效的修饰键（包括 Caps Lock 锁定状态）
+    pub modifiers: Modifiers,
+    // 来源设备标识：evdev 为 /dev/input/eventN 的 N；macOS 的 CGEvent 和 X11 核心事件无法区分设备，固定为 0
+    pub device: u32,
 }
 
-// 全局回调函数存储
-static mut GLOBAL_CALLBACK: Option<Box<dyn Fn(Event) + Send + Sync>> = None;
-
-// CGEventTap回调函数
-fn event_tap_callback(
-    _proxy: CGEventTapProxy,
-    event_type: CGEventType,
-    event: &CGEvent,
-) -> Option<CGEvent> {
-    // 只处理键盘按下事件
-    match event_type {
-        CGEventType::KeyDown => {
-            let keycode = event.get_integer_value_field(9);
-            let key = keycode_to_key(keycode as u16);
-
-            // 打印键盘事件信息
-            println!("键盘按下: {:?} (keycode: {})", key, keycode);
-            info!("键盘按下: {:?} (keycode: {})", key, keycode);
-
-            let keyboard_event = Event {
-                event_type: EventType::KeyPress(key),
-            };
-
-            // 调用全局回调函数
-            unsafe {
-                if let Some(ref callback) = GLOBAL_CALLBACK {
-                    callback(keyboard_event);
-                }
-            }
+impl Event {
+    // 以当前时间创建一个没有元数据的事件（脚本和回放使用）
+    pub fn new(event_type: EventType) -> Self {
+        Event {
+            event_type,
+            time: Instant::now(),
+            is_repeat: false,
+            modifiers: Modifiers::empty(),
+            device: 0,
         }
-        _ => {}
     }
+}
 
-    // 返回None表示不拦截事件，让它继续传递
-    None
+// 键盘事件的来源：各平台的监听后端，或测试、回放用的脚本。
+// run 把事件交给 EventSink，直到出错、自行结束或收到停止信号
+pub trait InputSource: Send + 'static {
+    fn name(&self) -> &str;
+
+    fn run(self: Box<Self>, sink: EventSink, stop: StopSignal) -> Result<(), Box<dyn std::error::Error>>;
 }
 
-// 提供与rdev兼容的listen函数，使用CGEventTap实现真实键盘监听
-pub fn listen<F>(callback: F) -> Result<(), Box<dyn std::error::Error>>
+// 在后台线程启动当前平台的键盘监听，每个事件都交给 callback；返回的句柄用于等待监听结束
+pub fn listen<F>(callback: F) -> Result<ListenerHandle, Box<dyn std::error::Error>>
 where
     F: Fn(Event) + Send + Sync + 'static,
 {
-    info!("🎯 启动CGEventTap键盘监听");
+    listen_with(default_source(), callback)
+}
 
-    // 将回调函数存储到全局变量
-    unsafe {
-        GLOBAL_CALLBACK = Some(Box::new(callback));
-    }
+// 使用指定的输入源监听
+pub fn listen_with<F>(source: Box<dyn InputSource>, callback: F) -> Result<ListenerHandle, Box<dyn std::error::Error>>
+where
+    F: Fn(Event) + Send + Sync + 'static,
+{
+    log::info!("键盘输入源: {}", source.name());
+    ListenerHandle::spawn("keyboard-listener", callback, move |sink, stop| source.run(sink, stop))
+}
+
+#[cfg(target_os = "macos")]
+fn default_source() -> Box<dyn InputSource> {
+    Box::new(macos::EventTapSource)
+}
 
-    // 创建要监听的事件类型向量
-    let event_types = vec![CGEventType::KeyDown];
+// Linux 优先读取 /dev/input；没有可读的键盘设备但有 X 会话时改用 XRecord。
+// 也可以用环境变量 KEY_SOUND_INPUT=evdev|x11 强制指定
+#[cfg(target_os = "linux")]
+fn default_source() -> Box<dyn InputSource> {
+    match std::env::var("KEY_SOUND_INPUT").as_deref() {
+        Ok("evdev") => Box::new(evdev::EvdevSource),
+        Ok("x11") => Box::new(x11::XRecordSource { display: None }),
+        _ => {
+            if !evdev::has_accessible_keyboard() && std::env::var_os("DISPLAY").is_some() {
+                log::info!("没有可读的键盘设备，改用X11监听");
+                Box::new(x11::XRecordSource { display: None })
+            } else {
+                Box::new(evdev::EvdevSource)
+            }
+        }
+    }
+}
 
-    // 创建CGEventTap
-    let event_tap = CGEventTap::new(
-        CGEventTapLocation::HID,
-        CGEventTapPlacement::HeadInsertEventTap,
-        CGEventTapOptions::ListenOnly,
-        event_types,
-        event_tap_callback,
-    );
+// 将PC set 1扫描码转换为Key枚举（Mechvibes 音效包使用这套编码，
+// 扩展键带有 0x0E / 0xE0 / 0xEE 前缀，如 0x0E1D 为右Ctrl，0xE048 为上方向键）
+pub fn scancode_to_key(scancode: u32) -> Key {
+    let extended = matches!(scancode >> 8, 0x0E | 0xE0 | 0xEE);
+    if extended {
+        return match scancode & 0xFF {
+            0x1C => Key::KpReturn,
+            0x1D => Key::ControlRight,
+            0x35 => Key::KpDivide,
+            0x37 => Key::PrintScreen,
+            0x38 => Key::AltGr,
+            0x45 => Key::Pause,
+            0x47 => Key::Home,
+            0x48 => Key::UpArrow,
+            0x49 => Key::PageUp,
+            0x4B => Key::LeftArrow,
+            0x4D => Key::RightArrow,
+            0x4F => Key::End,
+            0x50 => Key::DownArrow,
+            0x51 => Key::PageDown,
+            0x52 => Key::Insert,
+            0x53 => Key::Delete,
+            0x5B => Key::MetaLeft,
+            0x5C => Key::MetaRight,
+            _ => Key::Unknown(scancode),
+        };
+    }
 
-    match event_tap {
-        Ok(tap) => {
-            info!("✅ CGEventTap创建成功");
+    match scancode {
+        0x01 => Key::Escape,
+        0x02 => Key::Num1,
+        0x03 => Key::Num2,
+        0x04 => Key::Num3,
+        0x05 => Key::Num4,
+        0x06 => Key::Num5,
+        0x07 => Key::Num6,
+        0x08 => Key::Num7,
+        0x09 => Key::Num8,
+        0x0A => Key::Num9,
+        0x0B => Key::Num0,
+        0x0C => Key::Minus,
+        0x0D => Key::Equal,
+        0x0E => Key::Backspace,
+        0x0F => Key::Tab,
+        0x10 => Key::KeyQ,
+        0x11 => Key::KeyW,
+        0x12 => Key::KeyE,
+        0x13 => Key::KeyR,
+        0x14 => Key::KeyT,
+        0x15 => Key::KeyY,
+        0x16 => Key::KeyU,
+        0x17 => Key::KeyI,
+        0x18 => Key::KeyO,
+        0x19 => Key::KeyP,
+        0x1A => Key::LeftBracket,
+        0x1B => Key::RightBracket,
+        0x1C => Key::Return,
+        0x1D => Key::ControlLeft,
+        0x1E => Key::KeyA,
+        0x1F => Key::KeyS,
+        0x20 => Key::KeyD,
+        0x21 => Key::KeyF,
+        0x22 => Key::KeyG,
+        0x23 => Key::KeyH,
+        0x24 => Key::KeyJ,
+        0x25 => Key::KeyK,
+        0x26 => Key::KeyL,
+        0x27 => Key::SemiColon,
+        0x28 => Key::Quote,
+        0x29 => Key::BackQuote,
+        0x2A => Key::ShiftLeft,
+        0x2B => Key::BackSlash,
+        0x2C => Key::KeyZ,
+        0x2D => Key::KeyX,
+        0x2E => Key::KeyC,
+        0x2F => Key::KeyV,
+        0x30 => Key::KeyB,
+        0x31 => Key::KeyN,
+        0x32 => Key::KeyM,
+        0x33 => Key::Comma,
+        0x34 => Key::Dot,
+        0x35 => Key::Slash,
+        0x36 => Key::ShiftRight,
+        0x37 => Key::KpMultiply,
+        0x38 => Key::Alt,
+        0x39 => Key::Space,
+        0x3A => Key::CapsLock,
+        0x3B => Key::F1,
+        0x3C => Key::F2,
+        0x3D => Key::F3,
+        0x3E => Key::F4,
+        0x3F => Key::F5,
+        0x40 => Key::F6,
+        0x41 => Key::F7,
+        0x42 => Key::F8,
+        0x43 => Key::F9,
+        0x44 => Key::F10,
+        0x45 => Key::NumLock,
+        0x46 => Key::ScrollLock,
+        0x47 => Key::Kp7,
+        0x48 => Key::Kp8,
+        0x49 => Key::Kp9,
+        0x4A => Key::KpMinus,
+        0x4B => Key::Kp4,
+        0x4C => Key::Kp5,
+        0x4D => Key::Kp6,
+        0x4E => Key::KpPlus,
+        0x4F => Key::Kp1,
+        0x50 => Key::Kp2,
+        0x51 => Key::Kp3,
+        0x52 => Key::Kp0,
+        0x53 => Key::KpDelete,
+        0x56 => Key::IntlBackslash,
+        0x57 => Key::F11,
+        0x58 => Key::F12,
+        0x59 => Key::KpEqual,
+        0x64 => Key::F13,
+        0x65 => Key::F14,
+        0x66 => Key::F15,
+        0x67 => Key::F16,
+        0x68 => Key::F17,
+        0x69 => Key::F18,
+        0x6A => Key::F19,
+        0x6B => Key::F20,
+        // 0x70（カタカナ/ひらがな）和 0x7B（無変換）不是 Lang1/Lang2，保留为 Unknown
+        0x71 | 0xF1 => Key::Lang2,
+        0x72 | 0xF2 => Key::Lang1,
+        0x73 => Key::IntlRo,
+        0x7D => Key::IntlYen,
+        0x7E => Key::KpComma,
+        _ => Key::Unknown(scancode),
+    }
+}
 
-            // 创建运行循环源
-            let run_loop_source = tap.mach_port.create_runloop_source(0);
+// 将Linux evdev键码转换为Key枚举（bucklespring 音效包以该键码命名文件）
+pub fn evdev_code_to_key(code: u16) -> Key {
+    match code {
+        // 前 88 个键码与 set 1 扫描码一致
+        1..=88 => scancode_to_key(code as u32),
+        89 => Key::IntlRo,
+        96 => Key::KpReturn,
+        97 => Key::ControlRight,
+        98 => Key::KpDivide,
+        99 => Key::PrintScreen,
+        100 => Key::AltGr,
+        102 => Key::Home,
+        103 => Key::UpArrow,
+        104 => Key::PageUp,
+        105 => Key::LeftArrow,
+        106 => Key::RightArrow,
+        107 => Key::End,
+        108 => Key::DownArrow,
+        109 => Key::PageDown,
+        110 => Key::Insert,
+        111 => Key::Delete,
+        117 => Key::KpEqual,
+        119 => Key::Pause,
+        121 => Key::KpComma,
+        122 => Key::Lang1,
+        123 => Key::Lang2,
+        124 => Key::IntlYen,
+        125 => Key::MetaLeft,
+        126 => Key::MetaRight,
+        183 => Key::F13,
+        184 => Key::F14,
+        185 => Key::F15,
+        186 => Key::F16,
+        187 => Key::F17,
+        188 => Key::F18,
+        189 => Key::F19,
+        190 => Key::F20,
+        464 => Key::Function,
+        _ => Key::Unknown(code as u32),
+    }
+}
 
-            match run_loop_source {
-                Ok(source) => {
-                    info!("✅ 运行循环源创建成功");
+// macOS 虚拟键码表（Carbon kVK_* 常量），覆盖 ANSI、ISO 和 JIS 布局。
+// 每个键码和每个 Key 在表中只出现一次，因此可以双向查找。
+// 注意 ISO 键盘上 kVK_ISO_Section(0x0A) 是左Shift旁的键，0x32 是数字1左侧的键
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+const MACOS_KEYCODES: &[(u16, Key)] = &[
+    (0x00, Key::KeyA),
+    (0x01, Key::KeyS),
+    (0x02, Key::KeyD),
+    (0x03, Key::KeyF),
+    (0x04, Key::KeyH),
+    (0x05, Key::KeyG),
+    (0x06, Key::KeyZ),
+    (0x07, Key::KeyX),
+    (0x08, Key::KeyC),
+    (0x09, Key::KeyV),
+    (0x0A, Key::IntlBackslash),
+    (0x0B, Key::KeyB),
+    (0x0C, Key::KeyQ),
+    (0x0D, Key::KeyW),
+    (0x0E, Key::KeyE),
+    (0x0F, Key::KeyR),
+    (0x10, Key::KeyY),
+    (0x11, Key::KeyT),
+    (0x12, Key::Num1),
+    (0x13, Key::Num2),
+    (0x14, Key::Num3),
+    (0x15, Key::Num4),
+    (0x16, Key::Num6),
+    (0x17, Key::Num5),
+    (0x18, Key::Equal),
+    (0x19, Key::Num9),
+    (0x1A, Key::Num7),
+    (0x1B, Key::Minus),
+    (0x1C, Key::Num8),
+    (0x1D, Key::Num0),
+    (0x1E, Key::RightBracket),
+    (0x1F, Key::KeyO),
+    (0x20, Key::KeyU),
+    (0x21, Key::LeftBracket),
+    (0x22, Key::KeyI),
+    (0x23, Key::KeyP),
+    (0x24, Key::Return),
+    (0x25, Key::KeyL),
+    (0x26, Key::KeyJ),
+    (0x27, Key::Quote),
+    (0x28, Key::KeyK),
+    (0x29, Key::SemiColon),
+    (0x2A, Key::BackSlash),
+    (0x2B, Key::Comma),
+    (0x2C, Key::Slash),
+    (0x2D, Key::KeyN),
+    (0x2E, Key::KeyM),
+    (0x2F, Key::Dot),
+    (0x30, Key::Tab),
+    (0x31, Key::Space),
+    (0x32, Key::BackQuote),
+    (0x33, Key::Backspace),
+    (0x35, Key::Escape),
+    (0x36, Key::MetaRight),
+    (0x37, Key::MetaLeft),
+    (0x38, Key::ShiftLeft),
+    (0x39, Key::CapsLock),
+    (0x3A, Key::Alt),
+    (0x3B, Key::ControlLeft),
+    (0x3C, Key::ShiftRight),
+    (0x3D, Key::AltGr),
+    (0x3E, Key::ControlRight),
+    (0x3F, Key::Function),
+    (0x40, Key::F17),
+    (0x41, Key::KpDelete),
+    (0x43, Key::KpMultiply),
+    (0x45, Key::KpPlus),
+    (0x47, Key::NumLock), // 数字键盘 Clear 键
+    (0x4B, Key::KpDivide),
+    (0x4C, Key::KpReturn),
+    (0x4E, Key::KpMinus),
+    (0x4F, Key::F18),
+    (0x50, Key::F19),
+    (0x51, Key::KpEqual),
+    (0x52, Key::Kp0),
+    (0x53, Key::Kp1),
+    (0x54, Key::Kp2),
+    (0x55, Key::Kp3),
+    (0x56, Key::Kp4),
+    (0x57, Key::Kp5),
+    (0x58, Key::Kp6),
+    (0x59, Key::Kp7),
+    (0x5A, Key::F20),
+    (0x5B, Key::Kp8),
+    (0x5C, Key::Kp9),
+    (0x5D, Key::IntlYen),
+    (0x5E, Key::IntlRo),
+    (0x5F, Key::KpComma),
+    (0x60, Key::F5),
+    (0x61, Key::F6),
+    (0x62, Key::F7),
+    (0x63, Key::F3),
+    (0x64, Key::F8),
+    (0x65, Key::F9),
+    (0x66, Key::Lang2),
+    (0x67, Key::F11),
+    (0x68, Key::Lang1),
+    (0x69, Key::F13),
+    (0x6A, Key::F16),
+    (0x6B, Key::F14),
+    (0x6D, Key::F10),
+    (0x6F, Key::F12),
+    (0x71, Key::F15),
+    (0x72, Key::Insert), // Help 键位于 Insert 的位置
+    (0x73, Key::Home),
+    (0x74, Key::PageUp),
+    (0x75, Key::Delete),
+    (0x76, Key::F4),
+    (0x77, Key::End),
+    (0x78, Key::F2),
+    (0x79, Key::PageDown),
+    (0x7A, Key::F1),
+    (0x7B, Key::LeftArrow),
+    (0x7C, Key::RightArrow),
+    (0x7D, Key::DownArrow),
+    (0x7E, Key::UpArrow),
+];
 
-                    // 获取当前运行循环
-                    let run_loop = CFRunLoop::get_current();
+// 将macOS keyCode转换为我们的Key枚举
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn macos_keycode_to_key(keycode: u16) -> Key {
+    MACOS_KEYCODES.iter()
+        .find(|(code, _)| *code == keycode)
+        .map(|(_, key)| key.clone())
+        .unwrap_or(Key::Unknown(keycode as u32))
+}
 
-                    // 添加源到运行循环
-                    run_loop.add_source(&source, unsafe { kCFRunLoopCommonModes });
+// 将Key枚举转换回macOS keyCode，macOS 键盘上没有的键返回None
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub fn key_to_macos_keycode(key: &Key) -> Option<u16> {
+    if let Key::Unknown(code) = key {
+        return u16::try_from(*code).ok();
+    }
+    MACOS_KEYCODES.iter()
+        .find(|(_, candidate)| candidate == key)
+        .map(|(code, _)| *code)
+}
 
-                    // 启用事件监听
-                    tap.enable();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                    info!("🎧 开始监听键盘事件...");
-                    println!("键盘监听已启动，按任意键测试...");
+    #[test]
+    fn macos_keycodes_round_trip() {
+        for (code, key) in MACOS_KEYCODES {
+            assert_eq!(macos_keycode_to_key(*code), *key, "键码 0x{:02X}", code);
+            assert_eq!(key_to_macos_keycode(&macos_keycode_to_key(*code)), Some(*code), "{:?}", key);
+        }
+    }
 
-                    // 运行事件循环
-                    unsafe { CFRunLoopRun(); }
+    #[test]
+    fn macos_modifier_bits_have_keycodes() {
+        // macOS 后端只上报 FlagsChanged 事件键码对应的修饰键，每个修饰键位都要能反查键码
+        for bit in modifiers::MACOS_MODIFIER_BITS {
+            assert!(key_to_macos_keycode(&bit.key).is_some(), "{:?}", bit.key);
+        }
+    }
 
-                    Ok(())
-                }
-                Err(e) => {
-                    error!("❌ 创建运行循环源失败: {:?}", e);
-                    Err(format!("无法创建运行循环源: {:?}", e).into())
-                }
+    #[test]
+    fn macos_keycodes_have_no_duplicates() {
+        for (index, (code, key)) in MACOS_KEYCODES.iter().enumerate() {
+            for (other_code, other_key) in &MACOS_KEYCODES[index + 1..] {
+                assert_ne!(code, other_code, "键码 0x{:02X} 重复", code);
+                assert_ne!(key, other_key, "{:?} 重复", key);
             }
         }
-        Err(e) => {
-            error!("❌ CGEventTap创建失败: {:?}", e);
-            error!("⚠️  请检查辅助功能权限！");
-            error!("🔧 解决方案：系统偏好设置 → 安全性与隐私 → 隐私 → 辅助功能");
-            error!("   将此应用添加到辅助功能列表中");
-            Err(format!("CGEventTap创建失败: {:?}", e).into())
-        }
     }
-}
 
-// 将macOS keyCode转换为我们的Key枚举
-fn keycode_to_key(keycode: u16) -> Key {
-    match keycode {
-        36 => Key::Return,
-        53 => Key::Escape,
-        48 => Key::Tab,
-        49 => Key::Space,
-        51 => Key::Backspace,
-        117 => Key::Delete,
-        123 => Key::LeftArrow,
-        124 => Key::RightArrow,
-        125 => Key::DownArrow,
-        126 => Key::UpArrow,
-        18 => Key::Num1,
-        19 => Key::Num2,
-        20 => Key::Num3,
-        21 => Key::Num4,
-        23 => Key::Num5,
-        22 => Key::Num6,
-        26 => Key::Num7,
-        28 => Key::Num8,
-        25 => Key::Num9,
-        29 => Key::Num0,
-        12 => Key::KeyQ,
-        13 => Key::KeyW,
-        14 => Key::KeyE,
-        15 => Key::KeyR,
-        17 => Key::KeyT,
-        16 => Key::KeyY,
-        32 => Key::KeyU,
-        34 => Key::KeyI,
-        31 => Key::KeyO,
-        35 => Key::KeyP,
-        0 => Key::KeyA,
-        1 => Key::KeyS,
-        2 => Key::KeyD,
-        3 => Key::KeyF,
-        5 => Key::KeyG,
-        4 => Key::KeyH,
-        38 => Key::KeyJ,
-        40 => Key::KeyK,
-        37 => Key::KeyL,
-        6 => Key::KeyZ,
-        7 => Key::KeyX,
-        8 => Key::KeyC,
-        9 => Key::KeyV,
-        11 => Key::KeyB,
-        45 => Key::KeyN,
-        46 => Key::KeyM,
-        _ => Key::Unknown(keycode as u32),
+    #[test]
+    fn unknown_macos_keycode_round_trips() {
+        assert_eq!(macos_keycode_to_key(0xFF), Key::Unknown(0xFF));
+        assert_eq!(key_to_macos_keycode(&Key::Unknown(0xFF)), Some(0xFF));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn lang_keys_use_hangul_hanja_codes() {
+        assert_eq!(scancode_to_key(0xF2), Key::Lang1);
+        assert_eq!(scancode_to_key(0xF1), Key::Lang2);
+        assert_eq!(scancode_to_key(0x72), Key::Lang1);
+        assert_eq!(scancode_to_key(0x71), Key::Lang2);
+        assert_eq!(scancode_to_key(0x70), Key::Unknown(0x70));
+        assert_eq!(scancode_to_key(0x7B), Key::Unknown(0x7B));
+        assert_eq!(evdev_code_to_key(122), Key::Lang1);
+        assert_eq!(evdev_code_to_key(123), Key::Lang2);
+
+        // 三套编码中的 Lang1 / Lang2 对应 macOS 的 かな / 英数 键
+        assert_eq!(key_to_macos_keycode(&scancode_to_key(0xF2)), Some(0x68));
+        assert_eq!(key_to_macos_keycode(&scancode_to_key(0xF1)), Some(0x66));
+    }
+}
@@ -0,0 +1,417 @@
+// Linux 键盘和鼠标监听 - 直接读取 /dev/input/event* 设备
+use log::{debug, error, info, warn};
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::listener::{EventSink, StopSignal};
+use super::InputSource;
+use super::modifiers::HeldModifiers;
+use super::{evdev_code_to_key, Button, Event, EventType};
+
+const INPUT_DIR: &str = "/dev/input";
+const SYSFS_INPUT_DIR: &str = "/sys/class/input";
+
+// 扫描新插入键盘和鼠标的间隔
+const HOTPLUG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// struct input_event 的大小：timeval(两个 long) + type(u16) + code(u16) + value(i32)
+const LONG_SIZE: usize = std::mem::size_of::<std::os::raw::c_long>();
+pub const INPUT_EVENT_SIZE: usize = LONG_SIZE * 2 + 8;
+
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const SYN_REPORT: u16 = 0x00;
+// 内核的事件缓冲区溢出，之后到下一个 SYN_REPORT 之间的记录不完整
+const SYN_DROPPED: u16 = 0x03;
+// 键码 0x100 以上是鼠标、手柄等按钮
+const KEY_CODE_LIMIT: u16 = 0x100;
+const KEY_A: usize = 30;
+const KEY_SPACE: usize = 57;
+const BTN_LEFT: u16 = 0x110;
+const BTN_RIGHT: u16 = 0x111;
+const BTN_MIDDLE: u16 = 0x112;
+const BTN_SIDE: u16 = 0x113;
+const BTN_EXTRA: u16 = 0x114;
+const BTN_FORWARD: u16 = 0x115;
+const BTN_BACK: u16 = 0x116;
+// 高精度滚轮（REL_WHEEL_HI_RES）与普通滚轮同时上报，只使用普通滚轮
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+
+// 鼠标按钮码转换为 Button，触摸板的 BTN_TOUCH、手柄按钮等返回None
+fn evdev_code_to_button(code: u16) -> Option<Button> {
+    match code {
+        BTN_LEFT => Some(Button::Left),
+        BTN_RIGHT => Some(Button::Right),
+        BTN_MIDDLE => Some(Button::Middle),
+        BTN_SIDE | BTN_BACK => Some(Button::Back),
+        BTN_EXTRA | BTN_FORWARD => Some(Button::Forward),
+        _ => None,
+    }
+}
+
+// 一条原始 input_event 记录
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawInputEvent {
+    pub sec: i64,
+    pub usec: i64,
+    pub kind: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl RawInputEvent {
+    // 从一条完整的 input_event 字节记录（本机字节序）解析
+    pub fn parse(bytes: &[u8]) -> RawInputEvent {
+        let long_at = |offset: usize| -> i64 {
+            let mut raw = [0u8; 8];
+            raw[..LONG_SIZE].copy_from_slice(&bytes[offset..offset + LONG_SIZE]);
+            if LONG_SIZE == 8 {
+                i64::from_ne_bytes(raw)
+            } else {
+                i32::from_ne_bytes([raw[0], raw[1], raw[2], raw[3]]) as i64
+            }
+        };
+        let tail = LONG_SIZE * 2;
+        RawInputEvent {
+            sec: long_at(0),
+            usec: long_at(LONG_SIZE),
+            kind: u16::from_ne_bytes([bytes[tail], bytes[tail + 1]]),
+            code: u16::from_ne_bytes([bytes[tail + 2], bytes[tail + 3]]),
+            value: i32::from_ne_bytes([bytes[tail + 4], bytes[tail + 5], bytes[tail + 6], bytes[tail + 7]]),
+        }
+    }
+
+    // 转换为事件类型和是否自动重复，其他记录（同步、LED、鼠标移动等）返回None
+    pub fn to_event_type(self) -> Option<(EventType, bool)> {
+        match (self.kind, self.code) {
+            (EV_REL, REL_WHEEL) => return Some((EventType::Wheel { delta_x: 0, delta_y: self.value as i64 }, false)),
+            (EV_REL, REL_HWHEEL) => return Some((EventType::Wheel { delta_x: self.value as i64, delta_y: 0 }, false)),
+            (EV_KEY, code) if code >= KEY_CODE_LIMIT => {
+                let button = evdev_code_to_button(code)?;
+                return match self.value {
+                    1 => Some((EventType::ButtonPress(button), false)),
+                    0 => Some((EventType::ButtonRelease(button), false)),
+                    _ => None,
+                };
+            }
+            (EV_KEY, _) => {}
+            _ => return None,
+        }
+        let key = evdev_code_to_key(self.code);
+        match self.value {
+            // 1 为按下，2 为按住时的自动重复
+            1 => Some((EventType::KeyPress(key), false)),
+            2 => Some((EventType::KeyPress(key), true)),
+            0 => Some((EventType::KeyRelease(key), false)),
+            _ => None,
+        }
+    }
+}
+
+// input_event 字节流解码器，可以处理被拆分在多次读取中的记录，
+// 也可以直接喂入录制下来的设备字节流
+pub struct InputEventDecoder {
+    pending: Vec<u8>,
+    device: u32,
+    modifiers: HeldModifiers,
+    // 收到 SYN_DROPPED 后丢弃记录，直到下一个 SYN_REPORT
+    dropped: bool,
+}
+
+impl InputEventDecoder {
+    pub fn new(device: u32) -> Self {
+        InputEventDecoder {
+            pending: Vec::new(),
+            device,
+            modifiers: HeldModifiers::new(),
+            dropped: false,
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Event> {
+        self.pending.extend_from_slice(bytes);
+        let complete = self.pending.len() / INPUT_EVENT_SIZE * INPUT_EVENT_SIZE;
+        let time = Instant::now();
+        let mut events = Vec::new();
+        for record in self.pending[..complete].chunks_exact(INPUT_EVENT_SIZE) {
+            let raw = RawInputEvent::parse(record);
+            match (raw.kind, raw.code) {
+                (EV_SYN, SYN_DROPPED) => {
+                    warn!("设备 {} 的输入事件溢出，丢弃到下一次同步为止", self.device);
+                    self.dropped = true;
+                    self.modifiers.release_all();
+                    continue;
+                }
+                (EV_SYN, SYN_REPORT) if self.dropped => {
+                    self.dropped = false;
+                    continue;
+                }
+                _ if self.dropped => continue,
+                _ => {}
+            }
+            if let Some((event_type, is_repeat)) = raw.to_event_type() {
+                let modifiers = self.modifiers.update(&event_type, is_repeat);
+                events.push(Event {
+                    event_type,
+                    time,
+                    is_repeat,
+                    modifiers,
+                    device: self.device,
+                });
+            }
+        }
+        self.pending.drain(..complete);
+        events
+    }
+}
+
+// 从设备路径中取出编号，如 /dev/input/event3 为 3
+fn device_number(device: &Path) -> u32 {
+    device.file_name()
+        .and_then(|name| name.to_string_lossy().strip_prefix("event").and_then(|n| n.parse().ok()))
+        .unwrap_or(0)
+}
+
+// 解析 sysfs 中的能力位图（空格分隔的十六进制 long，高位在前），判断是否包含某个编码（键码或相对轴）
+fn has_capability(capabilities: &str, code: usize) -> bool {
+    let bits = LONG_SIZE * 8;
+    let words: Vec<&str> = capabilities.split_whitespace().rev().collect();
+    match words.get(code / bits).and_then(|word| u64::from_str_radix(word, 16).ok()) {
+        Some(word) => word & (1u64 << (code % bits)) != 0,
+        None => false,
+    }
+}
+
+// 读取设备在 sysfs 中的某类能力位图，如 "key"、"rel"
+fn read_capabilities(device: &Path, kind: &str) -> Option<String> {
+    let name = device.file_name()?;
+    let capabilities_path = Path::new(SYSFS_INPUT_DIR)
+        .join(name)
+        .join("device/capabilities")
+        .join(kind);
+    std::fs::read_to_string(capabilities_path).ok()
+}
+
+// 同时具有字母键和空格键的设备才当作键盘
+fn is_keyboard(device: &Path) -> bool {
+    match read_capabilities(device, "key") {
+        Some(capabilities) => {
+            has_capability(&capabilities, KEY_A) && has_capability(&capabilities, KEY_SPACE)
+        }
+        None => false,
+    }
+}
+
+// 具有左键或滚轮的设备当作鼠标（包括触摸板）
+fn is_mouse(device: &Path) -> bool {
+    let has_left_button = read_capabilities(device, "key")
+        .map(|capabilities| has_capability(&capabilities, BTN_LEFT as usize))
+        .unwrap_or(false);
+    let has_wheel = read_capabilities(device, "rel")
+        .map(|capabilities| has_capability(&capabilities, REL_WHEEL as usize))
+        .unwrap_or(false);
+    has_left_button || has_wheel
+}
+
+fn list_event_devices() -> std::io::Result<Vec<PathBuf>> {
+    let mut devices: Vec<PathBuf> = std::fs::read_dir(INPUT_DIR)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().starts_with("event"))
+                .unwrap_or(false)
+        })
+        .collect();
+    devices.sort();
+    Ok(devices)
+}
+
+// 是否至少有一个当前用户可以读取的键盘设备
+pub fn has_accessible_keyboard() -> bool {
+    match list_event_devices() {
+        Ok(devices) => devices.iter().any(|device| is_keyboard(device) && File::open(device).is_ok()),
+        Err(_) => false,
+    }
+}
+
+// 读取线程阻塞在 read 上，停止后会在下一次读到数据或设备断开时退出
+fn read_device(mut file: File, device: &Path, sink: &EventSink, stop: &StopSignal) {
+    let mut decoder = InputEventDecoder::new(device_number(device));
+    let mut buffer = [0u8; INPUT_EVENT_SIZE * 64];
+    loop {
+        let result = file.read(&mut buffer);
+        if stop.is_stopped() {
+            break;
+        }
+        match result {
+            Ok(0) => break,
+            Ok(n) => {
+                for event in decoder.feed(&buffer[..n]) {
+                    debug!("evdev事件: {:?} ({})", event.event_type, device.display());
+                    sink.emit(event);
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => {
+                // 设备拔出时读取会返回 ENODEV
+                info!("输入设备已断开: {} ({:?})", device.display(), e);
+                break;
+            }
+        }
+    }
+}
+
+// 读取所有键盘和鼠标设备，并定期扫描新插入的设备
+pub fn listen(sink: EventSink, stop: StopSignal) -> Result<(), Box<dyn std::error::Error>> {
+    info!("🎯 启动evdev键盘监听");
+
+    let opened: Arc<Mutex<HashSet<PathBuf>>> = Arc::new(Mutex::new(HashSet::new()));
+    let mut denied: HashSet<PathBuf> = HashSet::new();
+
+    // 第一次扫描失败说明系统没有输入设备目录，直接返回错误
+    list_event_devices().map_err(|e| format!("无法读取 {}: {:?}", INPUT_DIR, e))?;
+
+    loop {
+        let devices = match list_event_devices() {
+            Ok(devices) => devices,
+            Err(e) => {
+                error!("❌ 扫描输入设备失败: {:?}", e);
+                Vec::new()
+            }
+        };
+
+        for device in devices {
+            if opened.lock().unwrap().contains(&device) || !(is_keyboard(&device) || is_mouse(&device)) {
+                continue;
+            }
+            let file = match File::open(&device) {
+                Ok(file) => file,
+                Err(e) => {
+                    if denied.insert(device.clone()) {
+                        warn!("⚠️  无法打开输入设备 {}: {:?}", device.display(), e);
+                        warn!("🔧 解决方案：将当前用户加入 input 用户组后重新登录");
+                    }
+                    continue;
+                }
+            };
+
+            denied.remove(&device);
+            opened.lock().unwrap().insert(device.clone());
+            info!("✅ 开始监听输入设备: {}", device.display());
+
+            let sink = sink.clone();
+            let stop = stop.clone();
+            let opened = Arc::clone(&opened);
+            thread::spawn(move || {
+                read_device(file, &device, &sink, &stop);
+                opened.lock().unwrap().remove(&device);
+            });
+        }
+
+        if stop.wait_timeout(HOTPLUG_POLL_INTERVAL) {
+            info!("evdev键盘监听已停止");
+            return Ok(());
+        }
+    }
+}
+
+pub struct EvdevSource;
+
+impl InputSource for EvdevSource {
+    fn name(&self) -> &str {
+        "evdev"
+    }
+
+    fn run(self: Box<Self>, sink: EventSink, stop: StopSignal) -> Result<(), Box<dyn std::error::Error>> {
+        listen(sink, stop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard_adapter::{Key, Modifiers};
+
+    const EV_KEY_A: u16 = 30;
+    const EV_KEY_LEFTSHIFT: u16 = 42;
+
+    // 按本机字节序构造一条 input_event 记录
+    fn record(kind: u16, code: u16, value: i32) -> Vec<u8> {
+        let mut bytes = vec![0u8; LONG_SIZE * 2];
+        bytes.extend_from_slice(&kind.to_ne_bytes());
+        bytes.extend_from_slice(&code.to_ne_bytes());
+        bytes.extend_from_slice(&value.to_ne_bytes());
+        bytes
+    }
+
+    fn key(code: u16, value: i32) -> Vec<u8> {
+        record(EV_KEY, code, value)
+    }
+
+    fn syn(code: u16) -> Vec<u8> {
+        record(EV_SYN, code, 0)
+    }
+
+    fn decoded(events: &[Event]) -> Vec<(String, bool)> {
+        events.iter().map(|event| (format!("{:?}", event.event_type), event.is_repeat)).collect()
+    }
+
+    #[test]
+    fn parse_reads_native_record() {
+        let raw = RawInputEvent::parse(&key(EV_KEY_A, 2));
+        assert_eq!((raw.kind, raw.code, raw.value), (EV_KEY, EV_KEY_A, 2));
+    }
+
+    #[test]
+    fn key_values_map_to_press_repeat_release() {
+        let mut decoder = InputEventDecoder::new(3);
+        let bytes = [key(EV_KEY_A, 1), syn(SYN_REPORT), key(EV_KEY_A, 2), key(EV_KEY_A, 0)].concat();
+        let events = decoder.feed(&bytes);
+        assert_eq!(decoded(&events), vec![
+            ("KeyPress(KeyA)".to_string(), false),
+            ("KeyPress(KeyA)".to_string(), true),
+            ("KeyRelease(KeyA)".to_string(), false),
+        ]);
+        assert!(events.iter().all(|event| event.device == 3));
+    }
+
+    #[test]
+    fn record_split_across_reads() {
+        let mut decoder = InputEventDecoder::new(0);
+        let bytes = [key(EV_KEY_A, 1), key(EV_KEY_A, 0)].concat();
+        let (first, rest) = bytes.split_at(INPUT_EVENT_SIZE - 3);
+        assert!(decoder.feed(first).is_empty());
+        let (second, third) = rest.split_at(5);
+        assert_eq!(decoded(&decoder.feed(second)), vec![("KeyPress(KeyA)".to_string(), false)]);
+        assert_eq!(decoded(&decoder.feed(third)), vec![("KeyRelease(KeyA)".to_string(), false)]);
+    }
+
+    #[test]
+    fn syn_dropped_discards_until_next_report() {
+        let mut decoder = InputEventDecoder::new(0);
+        let shift = decoder.feed(&key(EV_KEY_LEFTSHIFT, 1));
+        assert!(shift[0].modifiers.contains(Modifiers::SHIFT));
+
+        let bytes = [
+            syn(SYN_DROPPED),
+            key(EV_KEY_A, 1),
+            key(EV_KEY_A, 0),
+            syn(SYN_REPORT),
+            key(EV_KEY_A, 1),
+        ].concat();
+        let events = decoder.feed(&bytes);
+        assert_eq!(decoded(&events), vec![("KeyPress(KeyA)".to_string(), false)]);
+        // 溢出时丢失了 Shift 的状态，之后的事件不再带 Shift
+        assert_eq!(events[0].modifiers, Modifiers::empty());
+        assert!(matches!(&events[0].event_type, EventType::KeyPress(Key::KeyA)));
+    }
+}
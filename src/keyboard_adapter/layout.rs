@@ -0,0 +1,190 @@
+// 键盘布局 - 每个按键在实体键盘上的位置（ANSI / ISO 全尺寸布局），用于按位置调整立体声声像
+use serde::{Deserialize, Serialize};
+
+use super::Key;
+
+// 实体键盘布局，两者只在 Enter、左Shift 和反斜杠附近不同
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    #[default]
+    Ansi,
+    Iso,
+}
+
+// 按键在键盘上的位置，以标准按键宽度（1u）为单位：
+// row 0 为功能键行，5 为空格行；column 为按键中心到主键区左边缘的距离
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyPosition {
+    pub row: f32,
+    pub column: f32,
+}
+
+// 主键区（Esc 到右Ctrl）宽 15u，声像以它的中心为正中
+const MAIN_BLOCK_WIDTH: f32 = 15.0;
+
+// 一段等宽的 1u 按键：(所在行, 第一个键的左边缘, 按键)
+const RUNS: &[(f32, f32, &[Key])] = &[
+    (0.0, 2.0, &[Key::F1, Key::F2, Key::F3, Key::F4]),
+    (0.0, 6.5, &[Key::F5, Key::F6, Key::F7, Key::F8]),
+    (0.0, 11.0, &[Key::F9, Key::F10, Key::F11, Key::F12]),
+    (0.0, 15.25, &[Key::PrintScreen, Key::ScrollLock, Key::Pause]),
+    // Apple 扩展键盘的 F13-F19 位于编辑区和小键盘上方
+    (0.0, 15.25, &[Key::F13, Key::F14, Key::F15]),
+    (0.0, 18.5, &[Key::F16, Key::F17, Key::F18, Key::F19]),
+    (1.0, 0.0, &[
+        Key::BackQuote, Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5, Key::Num6,
+        Key::Num7, Key::Num8, Key::Num9, Key::Num0, Key::Minus, Key::Equal,
+    ]),
+    (2.0, 1.5, &[
+        Key::KeyQ, Key::KeyW, Key::KeyE, Key::KeyR, Key::KeyT, Key::KeyY, Key::KeyU,
+        Key::KeyI, Key::KeyO, Key::KeyP, Key::LeftBracket, Key::RightBracket,
+    ]),
+    (3.0, 1.75, &[
+        Key::KeyA, Key::KeyS, Key::KeyD, Key::KeyF, Key::KeyG, Key::KeyH, Key::KeyJ,
+        Key::KeyK, Key::KeyL, Key::SemiColon, Key::Quote,
+    ]),
+    (4.0, 2.25, &[
+        Key::KeyZ, Key::KeyX, Key::KeyC, Key::KeyV, Key::KeyB, Key::KeyN, Key::KeyM,
+        Key::Comma, Key::Dot, Key::Slash,
+    ]),
+    (1.0, 15.25, &[Key::Insert, Key::Home, Key::PageUp]),
+    (2.0, 15.25, &[Key::Delete, Key::End, Key::PageDown]),
+    (5.0, 15.25, &[Key::LeftArrow, Key::DownArrow, Key::RightArrow]),
+    (1.0, 18.5, &[Key::NumLock, Key::KpDivide, Key::KpMultiply, Key::KpMinus]),
+    (2.0, 18.5, &[Key::Kp7, Key::Kp8, Key::Kp9]),
+    (3.0, 18.5, &[Key::Kp4, Key::Kp5, Key::Kp6]),
+    (4.0, 18.5, &[Key::Kp1, Key::Kp2, Key::Kp3]),
+];
+
+// 不等宽或位置特殊的按键：(所在行, 左边缘, 宽度)
+fn special_key(key: &Key, layout: Layout) -> Option<(f32, f32, f32)> {
+    let iso = layout == Layout::Iso;
+    Some(match key {
+        Key::Escape => (0.0, 0.0, 1.0),
+        Key::Backspace => (1.0, 13.0, 2.0),
+        Key::Tab => (2.0, 0.0, 1.5),
+        Key::CapsLock => (3.0, 0.0, 1.75),
+        // ISO 的 Enter 跨两行，反斜杠（# 键）移到 Enter 左侧
+        Key::Return if iso => (2.5, 13.75, 1.25),
+        Key::Return => (3.0, 12.75, 2.25),
+        Key::BackSlash if iso => (3.0, 12.75, 1.0),
+        Key::BackSlash => (2.0, 13.5, 1.5),
+        // ISO 的左Shift 较短，右侧多出一个键
+        Key::ShiftLeft if iso => (4.0, 0.0, 1.25),
+        Key::ShiftLeft => (4.0, 0.0, 2.25),
+        Key::IntlBackslash => (4.0, 1.25, 1.0),
+        Key::ShiftRight => (4.0, 12.25, 2.75),
+        Key::ControlLeft => (5.0, 0.0, 1.25),
+        Key::MetaLeft => (5.0, 1.25, 1.25),
+        Key::Alt => (5.0, 2.5, 1.25),
+        Key::Space => (5.0, 3.75, 6.25),
+        Key::AltGr => (5.0, 10.0, 1.25),
+        Key::MetaRight => (5.0, 11.25, 1.25),
+        Key::Function => (5.0, 12.5, 1.25),
+        Key::ControlRight => (5.0, 13.75, 1.25),
+        // JIS 键盘特有的键，位置取自 JIS 布局中最接近的位置
+        Key::IntlYen => (1.0, 13.0, 1.0),
+        Key::IntlRo => (4.0, 12.25, 1.0),
+        Key::Lang2 => (5.0, 3.75, 1.0),
+        Key::Lang1 => (5.0, 9.0, 1.0),
+        Key::UpArrow => (4.0, 16.25, 1.0),
+        Key::KpEqual => (1.0, 19.5, 1.0),
+        Key::KpPlus => (2.5, 21.5, 1.0),
+        Key::KpReturn => (4.5, 21.5, 1.0),
+        Key::Kp0 => (5.0, 18.5, 2.0),
+        Key::KpDelete => (5.0, 20.5, 1.0),
+        Key::KpComma => (5.0, 21.5, 1.0),
+        _ => return None,
+    })
+}
+
+impl Key {
+    // 按键在指定布局中的位置，没有固定位置的按键（如 Unknown、F20）返回None
+    pub fn position(&self, layout: Layout) -> Option<KeyPosition> {
+        if let Some((row, left, width)) = special_key(self, layout) {
+            return Some(KeyPosition { row, column: left + width / 2.0 });
+        }
+        RUNS.iter().find_map(|(row, left, keys)| {
+            let index = keys.iter().position(|key| key == self)?;
+            Some(KeyPosition { row: *row, column: left + index as f32 + 0.5 })
+        })
+    }
+
+    // 按键的立体声声像，-1.0 为最左，1.0 为最右；width 为 0 时总是居中。
+    // 主键区两端对应 ±width，编辑区和小键盘超出部分限制在 ±1.0
+    pub fn pan(&self, layout: Layout, width: f32) -> f32 {
+        match self.position(layout) {
+            Some(position) => {
+                let half = MAIN_BLOCK_WIDTH / 2.0;
+                ((position.column - half) / half * width).clamp(-1.0, 1.0)
+            }
+            None => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard_adapter::{evdev_code_to_key, macos_keycode_to_key};
+
+    // 各平台键码表能表示的所有按键
+    fn all_keys() -> Vec<Key> {
+        (0..=0x2FF).map(evdev_code_to_key)
+            .chain((0..=0x7F).map(macos_keycode_to_key))
+            .collect()
+    }
+
+    #[test]
+    fn pan_stays_within_range() {
+        for layout in [Layout::Ansi, Layout::Iso] {
+            for width in [0.5, 1.0, 2.0] {
+                for key in all_keys() {
+                    let pan = key.pan(layout, width);
+                    assert!((-1.0..=1.0).contains(&pan), "{:?} 在 {:?} 布局、宽度 {} 下声像为 {}", key, layout, width, pan);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn left_hand_keys_pan_left_and_right_hand_keys_pan_right() {
+        for layout in [Layout::Ansi, Layout::Iso] {
+            for key in [Key::Escape, Key::BackQuote, Key::KeyQ, Key::KeyA, Key::KeyZ, Key::ShiftLeft, Key::ControlLeft] {
+                assert!(key.pan(layout, 1.0) < 0.0, "{:?} 应在左侧", key);
+            }
+            for key in [Key::Backspace, Key::KeyP, Key::Quote, Key::Return, Key::Slash, Key::ShiftRight, Key::ControlRight, Key::Kp5] {
+                assert!(key.pan(layout, 1.0) > 0.0, "{:?} 应在右侧", key);
+            }
+        }
+        // 越靠外越偏
+        assert!(Key::KeyQ.pan(Layout::Ansi, 1.0) < Key::KeyT.pan(Layout::Ansi, 1.0));
+        assert!(Key::KeyY.pan(Layout::Ansi, 1.0) < Key::KeyP.pan(Layout::Ansi, 1.0));
+    }
+
+    #[test]
+    fn pan_scales_with_width() {
+        let full = Key::KeyA.pan(Layout::Ansi, 1.0);
+        assert!((Key::KeyA.pan(Layout::Ansi, 0.5) - full / 2.0).abs() < 1e-6);
+        assert_eq!(Key::KeyA.pan(Layout::Ansi, 0.0), 0.0);
+        // 小键盘超出主键区，被限制在最右
+        assert_eq!(Key::KpPlus.pan(Layout::Ansi, 1.0), 1.0);
+    }
+
+    #[test]
+    fn unknown_keys_stay_centred() {
+        for key in [Key::Unknown(0), Key::Unknown(0xFFFF), Key::F20] {
+            assert_eq!(key.position(Layout::Ansi), None);
+            assert_eq!(key.pan(Layout::Ansi, 1.0), 0.0);
+            assert_eq!(key.pan(Layout::Iso, 1.0), 0.0);
+        }
+    }
+
+    #[test]
+    fn iso_moves_return_and_backslash() {
+        assert_ne!(Key::Return.position(Layout::Ansi), Key::Return.position(Layout::Iso));
+        assert_ne!(Key::BackSlash.position(Layout::Ansi), Key::BackSlash.position(Layout::Iso));
+        assert_eq!(Key::KeyA.position(Layout::Ansi), Key::KeyA.position(Layout::Iso));
+    }
+}
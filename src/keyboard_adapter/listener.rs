@@ -0,0 +1,330 @@
+// 监听句柄 - 在后台线程运行键盘监听后端，把事件分发给所有订阅者，并支持停止
+use log::{debug, info};
+
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::Event;
+
+pub type SubscriptionId = u64;
+
+type Subscriber = Arc<dyn Fn(Event) + Send + Sync>;
+
+struct Subscribers {
+    next_id: SubscriptionId,
+    list: Vec<(SubscriptionId, Subscriber)>,
+}
+
+struct StopState {
+    stopped: Mutex<bool>,
+    changed: Condvar,
+    // 停止时要执行的动作，用于打断后端中阻塞的调用（如停止运行循环）
+    hooks: Mutex<Vec<Box<dyn FnOnce() + Send>>>,
+}
+
+// 后端用来判断是否需要退出的停止信号
+#[derive(Clone)]
+pub struct StopSignal {
+    state: Arc<StopState>,
+}
+
+impl StopSignal {
+    fn new() -> Self {
+        StopSignal {
+            state: Arc::new(StopState {
+                stopped: Mutex::new(false),
+                changed: Condvar::new(),
+                hooks: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        *self.state.stopped.lock().unwrap()
+    }
+
+    // 注册停止时执行的动作，已经停止则立即执行
+    pub fn on_stop<F>(&self, hook: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if self.is_stopped() {
+            hook();
+            return;
+        }
+        self.state.hooks.lock().unwrap().push(Box::new(hook));
+    }
+
+    // 最多等待 timeout，期间收到停止请求时提前返回；返回是否已停止
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let stopped = self.state.stopped.lock().unwrap();
+        let (stopped, _) = self.state.changed
+            .wait_timeout_while(stopped, timeout, |stopped| !*stopped)
+            .unwrap();
+        *stopped
+    }
+
+    fn stop(&self) {
+        {
+            let mut stopped = self.state.stopped.lock().unwrap();
+            if *stopped {
+                return;
+            }
+            *stopped = true;
+        }
+        self.state.changed.notify_all();
+
+        let hooks: Vec<_> = self.state.hooks.lock().unwrap().drain(..).collect();
+        for hook in hooks {
+            hook();
+        }
+    }
+}
+
+// 后端把事件交给它，由它转发给当前所有订阅者
+#[derive(Clone)]
+pub struct EventSink {
+    subscribers: Arc<RwLock<Subscribers>>,
+    stop: StopSignal,
+}
+
+impl EventSink {
+    pub fn emit(&self, event: Event) {
+        // 停止之后到达的事件直接丢弃
+        if self.stop.is_stopped() {
+            return;
+        }
+        // 复制一份订阅者列表再回调，允许回调中订阅或取消订阅
+        let subscribers: Vec<Subscriber> = self.subscribers.read().unwrap()
+            .list.iter()
+            .map(|(_, subscriber)| Arc::clone(subscriber))
+            .collect();
+        for subscriber in subscribers {
+            subscriber(event.clone());
+        }
+    }
+}
+
+// 正在运行的键盘监听。丢弃句柄不会停止监听，需要显式调用 stop()；
+// 所有方法都只需要共享引用，可以放进 Arc 在多个线程间共用
+pub struct ListenerHandle {
+    subscribers: Arc<RwLock<Subscribers>>,
+    stop: StopSignal,
+    thread: Mutex<Option<JoinHandle<Result<(), String>>>>,
+}
+
+impl ListenerHandle {
+    // 在名为 name 的后台线程中运行监听后端，callback 在后端启动前订阅，不会错过事件
+    pub fn spawn<F, R>(name: &str, callback: F, run: R) -> Result<Self, Box<dyn std::error::Error>>
+    where
+        F: Fn(Event) + Send + Sync + 'static,
+        R: FnOnce(EventSink, StopSignal) -> Result<(), Box<dyn std::error::Error>> + Send + 'static,
+    {
+        let first: Subscriber = Arc::new(callback);
+        let subscribers = Arc::new(RwLock::new(Subscribers {
+            next_id: 1,
+            list: vec![(0, first)],
+        }));
+        let stop = StopSignal::new();
+        let sink = EventSink {
+            subscribers: Arc::clone(&subscribers),
+            stop: stop.clone(),
+        };
+
+        let thread_stop = stop.clone();
+        let thread = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                // Box<dyn Error> 不能跨线程传递，转换为字符串
+                let result = run(sink, thread_stop.clone()).map_err(|e| e.to_string());
+                // 后端自行退出时也标记为已停止，执行已注册的清理动作
+                thread_stop.stop();
+                result
+            })?;
+
+        Ok(ListenerHandle {
+            subscribers,
+            stop,
+            thread: Mutex::new(Some(thread)),
+        })
+    }
+
+    // 添加一个事件订阅者，返回可用于取消订阅的标识；只会收到订阅之后的事件
+    pub fn subscribe<F>(&self, callback: F) -> SubscriptionId
+    where
+        F: Fn(Event) + Send + Sync + 'static,
+    {
+        let mut subscribers = self.subscribers.write().unwrap();
+        let id = subscribers.next_id;
+        subscribers.next_id += 1;
+        subscribers.list.push((id, Arc::new(callback)));
+        debug!("添加键盘事件订阅者: {}", id);
+        id
+    }
+
+    // 取消订阅，返回该订阅者是否存在
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        let mut subscribers = self.subscribers.write().unwrap();
+        let before = subscribers.list.len();
+        subscribers.list.retain(|(existing, _)| *existing != id);
+        debug!("取消键盘事件订阅者: {}", id);
+        subscribers.list.len() != before
+    }
+
+    // 请求停止监听，不等待后端退出；停止后不再分发任何事件
+    pub fn stop(&self) {
+        if !self.stop.is_stopped() {
+            info!("停止键盘监听");
+        }
+        self.stop.stop();
+    }
+
+    // 等待后端退出，返回后端的运行结果；只有第一次调用能取得结果
+    pub fn join(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let thread = self.thread.lock().unwrap().take().ok_or("键盘监听线程已经被等待过")?;
+        match thread.join() {
+            Ok(result) => result.map_err(|e| e.into()),
+            Err(_) => Err("键盘监听线程崩溃".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard_adapter::{listen_with, EventType, InputSource, Key};
+
+    use std::sync::mpsc;
+
+    // 发出一个事件后等待停止信号，或者按要求直接返回错误
+    struct FakeSource {
+        fail: bool,
+        stopped: mpsc::Sender<()>,
+    }
+
+    impl InputSource for FakeSource {
+        fn name(&self) -> &str {
+            "fake"
+        }
+
+        fn run(self: Box<Self>, sink: EventSink, stop: StopSignal) -> Result<(), Box<dyn std::error::Error>> {
+            if self.fail {
+                return Err("设备打不开".into());
+            }
+            let stopped = self.stopped.clone();
+            stop.on_stop(move || {
+                let _ = stopped.send(());
+            });
+            sink.emit(Event::new(EventType::KeyPress(Key::KeyA)));
+            while !stop.wait_timeout(Duration::from_secs(5)) {}
+            Ok(())
+        }
+    }
+
+    fn fake(fail: bool) -> (Box<FakeSource>, mpsc::Receiver<()>) {
+        let (stopped, receiver) = mpsc::channel();
+        (Box::new(FakeSource { fail, stopped }), receiver)
+    }
+
+    #[test]
+    fn stop_ends_backend_and_join_returns_ok() {
+        let (source, stop_hook) = fake(false);
+        let (events, received) = mpsc::channel();
+        let events = Mutex::new(events);
+        let handle = listen_with(source, move |event| {
+            events.lock().unwrap().send(event).unwrap();
+        }).unwrap();
+
+        let event = received.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(event.event_type, EventType::KeyPress(Key::KeyA)));
+
+        handle.stop();
+        stop_hook.recv_timeout(Duration::from_secs(5)).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn events_reach_every_subscriber_until_unsubscribed() {
+        let (source, _) = fake(false);
+        let (first, first_events) = mpsc::channel();
+        let (second, second_events) = mpsc::channel();
+        let first = Mutex::new(first);
+        let second = Mutex::new(second);
+        // 先订阅第二个回调再让后端发出事件，确保两个订阅者都能收到
+        let (go, wait) = mpsc::channel::<()>();
+        let wait = Mutex::new(wait);
+        let handle = ListenerHandle::spawn("fake", move |event| {
+            first.lock().unwrap().send(event).unwrap();
+        }, move |sink, stop| {
+            wait.lock().unwrap().recv().unwrap();
+            source.run(sink, stop)
+        }).unwrap();
+        let id = handle.subscribe(move |event| {
+            second.lock().unwrap().send(event).unwrap();
+        });
+        go.send(()).unwrap();
+
+        let timeout = Duration::from_secs(5);
+        assert!(matches!(first_events.recv_timeout(timeout).unwrap().event_type, EventType::KeyPress(Key::KeyA)));
+        assert!(matches!(second_events.recv_timeout(timeout).unwrap().event_type, EventType::KeyPress(Key::KeyA)));
+
+        assert!(handle.unsubscribe(id));
+        assert!(!handle.unsubscribe(id));
+        handle.stop();
+        handle.join().unwrap();
+        assert!(second_events.try_recv().is_err());
+    }
+
+    #[test]
+    fn join_twice_is_an_error() {
+        let (source, _) = fake(true);
+        let handle = listen_with(source, |_| {}).unwrap();
+        assert!(handle.join().is_err());
+        assert_eq!(handle.join().unwrap_err().to_string(), "键盘监听线程已经被等待过");
+    }
+
+    #[test]
+    fn double_stop_runs_hooks_once() {
+        let (source, stop_hook) = fake(false);
+        let handle = listen_with(source, |_| {}).unwrap();
+        handle.stop();
+        handle.stop();
+        handle.join().unwrap();
+        assert_eq!(stop_hook.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn backend_error_is_returned_from_join() {
+        let (source, _) = fake(true);
+        let handle = listen_with(source, |_| {}).unwrap();
+        let error = handle.join().unwrap_err();
+        assert_eq!(error.to_string(), "设备打不开");
+    }
+
+    #[test]
+    fn events_after_stop_are_dropped() {
+        let stop = StopSignal::new();
+        let (events, received) = mpsc::channel();
+        let events = Mutex::new(events);
+        let subscribers = Arc::new(RwLock::new(Subscribers {
+            next_id: 1,
+            list: vec![(0, Arc::new(move |event| events.lock().unwrap().send(event).unwrap()) as Subscriber)],
+        }));
+        let sink = EventSink { subscribers, stop: stop.clone() };
+        sink.emit(Event::new(EventType::KeyPress(Key::KeyA)));
+        stop.stop();
+        sink.emit(Event::new(EventType::KeyRelease(Key::KeyA)));
+        assert_eq!(received.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn on_stop_after_stop_runs_immediately() {
+        let stop = StopSignal::new();
+        stop.stop();
+        let (ran, receiver) = mpsc::channel();
+        stop.on_stop(move || ran.send(()).unwrap());
+        assert!(receiver.try_recv().is_ok());
+        assert!(stop.wait_timeout(Duration::from_secs(5)));
+    }
+}
@@ -0,0 +1,210 @@
+// macOS 键盘和鼠标监听 - 使用CGEventTap实现
+use log::{debug, error, info};
+use core_graphics::event::{CGEvent, CGEventTap, EventField, CGEventTapLocation, CGEventTapOptions, CGEventTapPlacement, CGEventType};
+use core_foundation::runloop::{CFRunLoop, kCFRunLoopCommonModes, kCFRunLoopDefaultMode};
+
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+use super::modifiers::{flags_to_modifiers, ModifierTracker, MACOS_MODIFIER_BITS, MACOS_MODIFIER_MASKS, MACOS_SECONDARY_FN};
+use super::listener::{EventSink, StopSignal};
+use super::InputSource;
+use super::{key_to_macos_keycode, macos_keycode_to_key, Button, Event, EventType};
+
+// 运行循环每次最多运行这么久，然后检查是否已请求停止
+const RUN_LOOP_SLICE: Duration = Duration::from_millis(500);
+
+// 用 CGEvent 自带的字段补全事件元数据，flags 为修饰键标志位（按键事件使用纠正过的标志位）
+fn make_event(event_type: EventType, event: &CGEvent, flags: u64) -> Event {
+    Event {
+        event_type,
+        time: Instant::now(),
+        is_repeat: event.get_integer_value_field(EventField::KEYBOARD_EVENT_AUTOREPEAT) != 0,
+        modifiers: flags_to_modifiers(flags, MACOS_MODIFIER_MASKS),
+        // CGEvent 不携带来源设备；KEYBOARD_EVENT_KEYBOARD_TYPE 是键盘布局类型（ANSI/ISO/JIS），不能当作设备标识
+        device: 0,
+    }
+}
+
+// 普通按键事件的标志位：同步 Caps Lock 等状态，SecondaryFn 位沿用 FlagsChanged 得到的 Fn 状态
+fn key_event_flags(modifier_tracker: &RefCell<ModifierTracker>, event: &CGEvent) -> u64 {
+    let mut tracker = modifier_tracker.borrow_mut();
+    tracker.sync(event.get_flags().bits(), MACOS_SECONDARY_FN);
+    tracker.flags()
+}
+
+// CGEvent 的按钮编号：0 左键，1 右键，2 中键，3、4 为后退、前进侧键
+fn mouse_button(event: &CGEvent) -> Button {
+    match event.get_integer_value_field(EventField::MOUSE_EVENT_BUTTON_NUMBER) {
+        0 => Button::Left,
+        1 => Button::Right,
+        2 => Button::Middle,
+        3 => Button::Back,
+        4 => Button::Forward,
+        other => Button::Unknown(other as u32),
+    }
+}
+
+// CGEventTap回调函数
+fn event_tap_callback(
+    sink: &EventSink,
+    modifier_tracker: &RefCell<ModifierTracker>,
+    event_type: CGEventType,
+    event: &CGEvent,
+) -> Option<CGEvent> {
+    // 只处理键盘按下、松开、修饰键变化以及鼠标按钮和滚轮事件
+    match event_type {
+        CGEventType::KeyDown => {
+            let flags = key_event_flags(modifier_tracker, event);
+
+            let keycode = event.get_integer_value_field(9);
+            let key = macos_keycode_to_key(keycode as u16);
+
+            // 打印键盘事件信息
+            println!("键盘按下: {:?} (keycode: {})", key, keycode);
+            info!("键盘按下: {:?} (keycode: {})", key, keycode);
+
+            sink.emit(make_event(EventType::KeyPress(key), event, flags));
+        }
+        CGEventType::KeyUp => {
+            let flags = key_event_flags(modifier_tracker, event);
+
+            let keycode = event.get_integer_value_field(9);
+            let key = macos_keycode_to_key(keycode as u16);
+
+            debug!("键盘松开: {:?} (keycode: {})", key, keycode);
+
+            sink.emit(make_event(EventType::KeyRelease(key), event, flags));
+        }
+        CGEventType::FlagsChanged => {
+            // 修饰键只通过 FlagsChanged 事件上报，需要对比前后标志位才能知道是哪个键按下或松开
+            let flags = event.get_flags().bits();
+            let keycode = event.get_integer_value_field(EventField::KEYBOARD_EVENT_KEYCODE) as u16;
+            let changes = modifier_tracker.borrow_mut().update(flags);
+
+            for event_type in changes {
+                // 只上报本次事件的键码对应的修饰键，其他位的变化是之前未同步的状态，不是真实的按键
+                let (EventType::KeyPress(key) | EventType::KeyRelease(key)) = &event_type else { continue };
+                if key_to_macos_keycode(key) != Some(keycode) {
+                    debug!("忽略修饰键状态变化: {:?} (keycode: {}, flags: {:#x})", event_type, keycode, flags);
+                    continue;
+                }
+                debug!("修饰键变化: {:?} (flags: {:#x})", event_type, flags);
+                sink.emit(make_event(event_type, event, flags));
+            }
+        }
+        CGEventType::LeftMouseDown | CGEventType::RightMouseDown | CGEventType::OtherMouseDown => {
+            let button = mouse_button(event);
+            debug!("鼠标按下: {:?}", button);
+            sink.emit(make_event(EventType::ButtonPress(button), event, event.get_flags().bits()));
+        }
+        CGEventType::LeftMouseUp | CGEventType::RightMouseUp | CGEventType::OtherMouseUp => {
+            let button = mouse_button(event);
+            debug!("鼠标松开: {:?}", button);
+            sink.emit(make_event(EventType::ButtonRelease(button), event, event.get_flags().bits()));
+        }
+        CGEventType::ScrollWheel => {
+            // 以行为单位的滚动量；触摸板的细微滚动不足一行时为 0，不产生事件
+            let delta_y = event.get_integer_value_field(EventField::SCROLL_WHEEL_EVENT_DELTA_AXIS_1);
+            let delta_x = event.get_integer_value_field(EventField::SCROLL_WHEEL_EVENT_DELTA_AXIS_2);
+            if delta_x != 0 || delta_y != 0 {
+                debug!("滚轮滚动: x={}, y={}", delta_x, delta_y);
+                sink.emit(make_event(EventType::Wheel { delta_x, delta_y }, event, event.get_flags().bits()));
+            }
+        }
+        _ => {}
+    }
+
+    // 返回None表示不拦截事件，让它继续传递
+    None
+}
+
+// 使用CGEventTap实现真实键盘监听
+pub fn listen(sink: EventSink, stop: StopSignal) -> Result<(), Box<dyn std::error::Error>> {
+    info!("🎯 启动CGEventTap键盘监听");
+
+    let modifier_tracker = RefCell::new(ModifierTracker::new(MACOS_MODIFIER_BITS));
+
+    // 创建要监听的事件类型向量
+    let event_types = vec![
+        CGEventType::KeyDown,
+        CGEventType::KeyUp,
+        CGEventType::FlagsChanged,
+        CGEventType::LeftMouseDown,
+        CGEventType::LeftMouseUp,
+        CGEventType::RightMouseDown,
+        CGEventType::RightMouseUp,
+        CGEventType::OtherMouseDown,
+        CGEventType::OtherMouseUp,
+        CGEventType::ScrollWheel,
+    ];
+
+    // 创建CGEventTap
+    let event_tap = CGEventTap::new(
+        CGEventTapLocation::HID,
+        CGEventTapPlacement::HeadInsertEventTap,
+        CGEventTapOptions::ListenOnly,
+        event_types,
+        move |_proxy, event_type, event| event_tap_callback(&sink, &modifier_tracker, event_type, event),
+    );
+
+    match event_tap {
+        Ok(tap) => {
+            info!("✅ CGEventTap创建成功");
+
+            // 创建运行循环源
+            let run_loop_source = tap.mach_port.create_runloop_source(0);
+
+            match run_loop_source {
+                Ok(source) => {
+                    info!("✅ 运行循环源创建成功");
+
+                    // 获取当前运行循环
+                    let run_loop = CFRunLoop::get_current();
+
+                    // 添加源到运行循环
+                    run_loop.add_source(&source, unsafe { kCFRunLoopCommonModes });
+
+                    // 启用事件监听
+                    tap.enable();
+
+                    info!("🎧 开始监听键盘事件...");
+                    println!("键盘监听已启动，按任意键测试...");
+
+                    // 停止时打断运行循环，否则分段运行并检查停止标志
+                    let stop_run_loop = run_loop.clone();
+                    stop.on_stop(move || stop_run_loop.stop());
+                    while !stop.is_stopped() {
+                        CFRunLoop::run_in_mode(unsafe { kCFRunLoopDefaultMode }, RUN_LOOP_SLICE, false);
+                    }
+
+                    info!("CGEventTap键盘监听已停止");
+                    Ok(())
+                }
+                Err(e) => {
+                    error!("❌ 创建运行循环源失败: {:?}", e);
+                    Err(format!("无法创建运行循环源: {:?}", e).into())
+                }
+            }
+        }
+        Err(e) => {
+            error!("❌ CGEventTap创建失败: {:?}", e);
+            error!("⚠️  请检查辅助功能权限！");
+            error!("🔧 解决方案：系统偏好设置 → 安全性与隐私 → 隐私 → 辅助功能");
+            error!("   将此应用添加到辅助功能列表中");
+            Err(format!("CGEventTap创建失败: {:?}", e).into())
+        }
+    }
+}
+
+pub struct EventTapSource;
+
+impl InputSource for EventTapSource {
+    fn name(&self) -> &str {
+        "CGEventTap"
+    }
+
+    fn run(self: Box<Self>, sink: EventSink, stop: StopSignal) -> Result<(), Box<dyn std::error::Error>> {
+        listen(sink, stop)
+    }
+}
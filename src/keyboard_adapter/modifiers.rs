@@ -0,0 +1,298 @@
+// 修饰键 - 修饰键集合，以及根据标志位或按键事件推导修饰键状态的工具，与平台无关
+use serde::{Deserialize, Serialize};
+
+use super::{EventType, Key};
+
+// 事件发生时生效的修饰键集合（位集合，不区分左右，序列化为整数）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub const SHIFT: Modifiers = Modifiers(0x01);
+    pub const CONTROL: Modifiers = Modifiers(0x02);
+    pub const ALT: Modifiers = Modifiers(0x04);
+    pub const META: Modifiers = Modifiers(0x08);
+    pub const CAPS_LOCK: Modifiers = Modifiers(0x10);
+    pub const FUNCTION: Modifiers = Modifiers(0x20);
+
+    pub const fn empty() -> Self {
+        Modifiers(0)
+    }
+
+    #[cfg(test)]
+    pub fn contains(self, other: Modifiers) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Modifiers) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Modifiers) {
+        self.0 &= !other.0;
+    }
+}
+
+impl Key {
+    // 该按键对应的修饰键位，普通按键返回None
+    pub fn modifier(&self) -> Option<Modifiers> {
+        match self {
+            Key::ShiftLeft | Key::ShiftRight => Some(Modifiers::SHIFT),
+            Key::ControlLeft | Key::ControlRight => Some(Modifiers::CONTROL),
+            Key::Alt | Key::AltGr => Some(Modifiers::ALT),
+            Key::MetaLeft | Key::MetaRight => Some(Modifiers::META),
+            Key::CapsLock => Some(Modifiers::CAPS_LOCK),
+            Key::Function => Some(Modifiers::FUNCTION),
+            _ => None,
+        }
+    }
+}
+
+// 一个修饰键在标志位中的位置
+#[cfg(any(target_os = "macos", test))]
+pub struct ModifierBit {
+    pub mask: u64,
+    pub key: Key,
+    // 锁定键（如 Caps Lock）的标志位表示锁定状态而不是按住状态，
+    // 每次翻转都当作一次完整的按下+松开
+    pub toggle: bool,
+}
+
+#[cfg(any(target_os = "macos", test))]
+const fn bit(mask: u64, key: Key) -> ModifierBit {
+    ModifierBit { mask, key, toggle: false }
+}
+
+// macOS CGEventFlags：区分左右的设备相关位（IOKit NX_DEVICE*KEYMASK）和 Caps Lock / Fn 位
+#[cfg(any(target_os = "macos", test))]
+pub const MACOS_MODIFIER_BITS: &[ModifierBit] = &[
+    bit(0x0000_0001, Key::ControlLeft),
+    bit(0x0000_0002, Key::ShiftLeft),
+    bit(0x0000_0004, Key::ShiftRight),
+    bit(0x0000_0008, Key::MetaLeft),
+    bit(0x0000_0010, Key::MetaRight),
+    bit(0x0000_0020, Key::Alt),
+    bit(0x0000_0040, Key::AltGr),
+    bit(0x0000_2000, Key::ControlRight),
+    ModifierBit { mask: 0x0001_0000, key: Key::CapsLock, toggle: true },
+    bit(0x0080_0000, Key::Function),
+];
+
+// macOS 在方向键、F 键、Home/End、PageUp/PageDown 的按键事件上也会设置 SecondaryFn 位，
+// 这时它不表示 Fn 被按住
+#[cfg(any(target_os = "macos", test))]
+pub const MACOS_SECONDARY_FN: u64 = 0x0080_0000;
+
+// macOS CGEventFlags 中不区分左右的修饰键位
+#[cfg(any(target_os = "macos", test))]
+pub const MACOS_MODIFIER_MASKS: &[(u64, Modifiers)] = &[
+    (0x0001_0000, Modifiers::CAPS_LOCK),
+    (0x0002_0000, Modifiers::SHIFT),
+    (0x0004_0000, Modifiers::CONTROL),
+    (0x0008_0000, Modifiers::ALT),
+    (0x0010_0000, Modifiers::META),
+    (0x0080_0000, Modifiers::FUNCTION),
+];
+
+// X11 核心事件 state 字段中的修饰键位（Mod1 通常为 Alt，Mod4 通常为 Super）
+#[cfg(any(target_os = "linux", test))]
+pub const X11_MODIFIER_MASKS: &[(u64, Modifiers)] = &[
+    (0x0001, Modifiers::SHIFT),
+    (0x0002, Modifiers::CAPS_LOCK),
+    (0x0004, Modifiers::CONTROL),
+    (0x0008, Modifiers::ALT),
+    (0x0040, Modifiers::META),
+];
+
+// 按掩码表把平台的标志位转换为修饰键集合
+pub fn flags_to_modifiers(flags: u64, masks: &[(u64, Modifiers)]) -> Modifiers {
+    let mut modifiers = Modifiers::empty();
+    for (mask, modifier) in masks {
+        if flags & mask != 0 {
+            modifiers.insert(*modifier);
+        }
+    }
+    modifiers
+}
+
+// 记录上一次的标志位，每次收到新的标志位时给出变化的修饰键事件
+#[cfg(any(target_os = "macos", test))]
+pub struct ModifierTracker {
+    bits: &'static [ModifierBit],
+    flags: u64,
+}
+
+#[cfg(any(target_os = "macos", test))]
+impl ModifierTracker {
+    pub const fn new(bits: &'static [ModifierBit]) -> Self {
+        ModifierTracker { bits, flags: 0 }
+    }
+
+    // 只同步状态而不产生事件，用普通按键事件携带的标志位纠正启动时未知的锁定状态；
+    // ignore 中的位在普通按键事件上不可信，保留原来的状态
+    pub fn sync(&mut self, flags: u64, ignore: u64) {
+        self.flags = (flags & !ignore) | (self.flags & ignore);
+    }
+
+    // 当前的标志位
+    pub fn flags(&self) -> u64 {
+        self.flags
+    }
+
+    // 输入新的标志位，按表中顺序返回本次变化产生的事件
+    pub fn update(&mut self, flags: u64) -> Vec<EventType> {
+        let changed = self.flags ^ flags;
+        self.flags = flags;
+
+        let mut events = Vec::new();
+        for bit in self.bits.iter().filter(|bit| changed & bit.mask != 0) {
+            if bit.toggle {
+                events.push(EventType::KeyPress(bit.key.clone()));
+                events.push(EventType::KeyRelease(bit.key.clone()));
+            } else if flags & bit.mask != 0 {
+                events.push(EventType::KeyPress(bit.key.clone()));
+            } else {
+                events.push(EventType::KeyRelease(bit.key.clone()));
+            }
+        }
+        events
+    }
+}
+
+// 没有标志位可读的后端（如 evdev）根据按键事件自己维护修饰键状态
+#[cfg(any(target_os = "linux", test))]
+pub struct HeldModifiers {
+    held: Vec<Key>,
+    caps_lock: bool,
+}
+
+#[cfg(any(target_os = "linux", test))]
+impl HeldModifiers {
+    pub fn new() -> Self {
+        HeldModifiers { held: Vec::new(), caps_lock: false }
+    }
+
+    // 处理一次按键事件，返回事件发生后生效的修饰键
+    pub fn update(&mut self, event_type: &EventType, is_repeat: bool) -> Modifiers {
+        match event_type {
+            EventType::KeyPress(Key::CapsLock) if !is_repeat => self.caps_lock = !self.caps_lock,
+            EventType::KeyPress(key) if key.modifier().is_some() && !self.held.contains(key) => {
+                self.held.push(key.clone());
+            }
+            EventType::KeyRelease(key) => self.held.retain(|held| held != key),
+            _ => {}
+        }
+        self.current()
+    }
+
+    // 丢失事件后不再知道哪些修饰键仍被按住，全部当作已松开；锁定状态不受影响
+    pub fn release_all(&mut self) {
+        self.held.clear();
+    }
+
+    pub fn current(&self) -> Modifiers {
+        let mut modifiers = Modifiers::empty();
+        for modifier in self.held.iter().filter_map(Key::modifier) {
+            modifiers.insert(modifier);
+        }
+        if self.caps_lock {
+            modifiers.insert(Modifiers::CAPS_LOCK);
+        }
+        modifiers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(key: Key) -> EventType {
+        EventType::KeyPress(key)
+    }
+
+    fn release(key: Key) -> EventType {
+        EventType::KeyRelease(key)
+    }
+
+    #[test]
+    fn tracker_reports_left_and_right_shift_separately() {
+        let mut tracker = ModifierTracker::new(MACOS_MODIFIER_BITS);
+        assert_eq!(tracker.update(0x2), vec![press(Key::ShiftLeft)]);
+        assert_eq!(tracker.update(0x6), vec![press(Key::ShiftRight)]);
+        assert_eq!(tracker.update(0x4), vec![release(Key::ShiftLeft)]);
+        assert_eq!(tracker.update(0x0), vec![release(Key::ShiftRight)]);
+    }
+
+    #[test]
+    fn tracker_turns_caps_lock_flip_into_press_and_release() {
+        let mut tracker = ModifierTracker::new(MACOS_MODIFIER_BITS);
+        let both = vec![press(Key::CapsLock), release(Key::CapsLock)];
+        assert_eq!(tracker.update(0x1_0000), both);
+        assert_eq!(tracker.update(0x0), both);
+    }
+
+    #[test]
+    fn tracker_sync_updates_state_without_events() {
+        let mut tracker = ModifierTracker::new(MACOS_MODIFIER_BITS);
+        // 启动时 Caps Lock 已锁定，从普通按键的标志位得知
+        tracker.sync(0x1_0000, MACOS_SECONDARY_FN);
+        assert!(tracker.update(0x1_0000).is_empty());
+        assert_eq!(tracker.update(0x1_0002), vec![press(Key::ShiftLeft)]);
+    }
+
+    #[test]
+    fn arrow_key_secondary_fn_is_not_a_held_fn() {
+        let mut tracker = ModifierTracker::new(MACOS_MODIFIER_BITS);
+        // 方向键事件带有 SecondaryFn 位
+        tracker.sync(MACOS_SECONDARY_FN, MACOS_SECONDARY_FN);
+        assert_eq!(flags_to_modifiers(tracker.flags(), MACOS_MODIFIER_MASKS), Modifiers::empty());
+        // 接下来按下 Shift，不应出现 Fn 松开
+        assert_eq!(tracker.update(0x2), vec![press(Key::ShiftLeft)]);
+        assert!(tracker.update(0x0).iter().all(|event| !matches!(event, EventType::KeyRelease(Key::Function))));
+    }
+
+    #[test]
+    fn held_fn_survives_arrow_key_sync() {
+        let mut tracker = ModifierTracker::new(MACOS_MODIFIER_BITS);
+        assert_eq!(tracker.update(MACOS_SECONDARY_FN), vec![press(Key::Function)]);
+        // 按住 Fn 时按方向键，标志位中的 Fn 位可能被系统清除或保留，都不改变已知的状态
+        tracker.sync(0x0, MACOS_SECONDARY_FN);
+        assert!(flags_to_modifiers(tracker.flags(), MACOS_MODIFIER_MASKS).contains(Modifiers::FUNCTION));
+        assert_eq!(tracker.update(0x0), vec![release(Key::Function)]);
+    }
+
+    #[test]
+    fn held_keeps_shift_until_both_sides_released() {
+        let mut held = HeldModifiers::new();
+        assert_eq!(held.update(&press(Key::ShiftLeft), false), Modifiers::SHIFT);
+        assert_eq!(held.update(&press(Key::ShiftRight), false), Modifiers::SHIFT);
+        assert_eq!(held.update(&release(Key::ShiftLeft), false), Modifiers::SHIFT);
+        assert_eq!(held.update(&release(Key::ShiftRight), false), Modifiers::empty());
+    }
+
+    #[test]
+    fn held_toggles_caps_lock_only_on_first_press() {
+        let mut held = HeldModifiers::new();
+        assert_eq!(held.update(&press(Key::CapsLock), false), Modifiers::CAPS_LOCK);
+        assert_eq!(held.update(&press(Key::CapsLock), true), Modifiers::CAPS_LOCK);
+        assert_eq!(held.update(&release(Key::CapsLock), false), Modifiers::CAPS_LOCK);
+        assert_eq!(held.update(&press(Key::CapsLock), false), Modifiers::empty());
+    }
+
+    #[test]
+    fn release_all_keeps_caps_lock() {
+        let mut held = HeldModifiers::new();
+        held.update(&press(Key::CapsLock), false);
+        held.update(&press(Key::ControlLeft), false);
+        held.release_all();
+        assert_eq!(held.current(), Modifiers::CAPS_LOCK);
+    }
+
+    #[test]
+    fn flags_map_through_mask_table() {
+        let modifiers = flags_to_modifiers(0x0002_0000 | 0x0010_0000, MACOS_MODIFIER_MASKS);
+        assert!(modifiers.contains(Modifiers::SHIFT));
+        assert!(modifiers.contains(Modifiers::META));
+        assert!(!modifiers.contains(Modifiers::CONTROL));
+    }
+}
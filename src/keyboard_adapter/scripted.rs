@@ -0,0 +1,62 @@
+// 脚本输入源 - 按给定的间隔依次发出预设事件，用于测试和回放，不依赖任何平台接口
+use log::{debug, info};
+
+use std::time::{Duration, Instant};
+
+use super::listener::{EventSink, StopSignal};
+use super::{Event, InputSource};
+#[cfg(test)]
+use super::{EventType, Key};
+
+pub struct ScriptedSource {
+    // (距上一个事件的延迟, 事件)
+    script: Vec<(Duration, Event)>,
+}
+
+impl ScriptedSource {
+    // 由时间线创建：每个事件带有距开始的偏移，按偏移排序后换算为相邻事件的间隔
+    pub fn from_timeline(mut timeline: Vec<(Duration, Event)>) -> Self {
+        timeline.sort_by_key(|(offset, _)| *offset);
+        let mut previous = Duration::ZERO;
+        let script = timeline.into_iter()
+            .map(|(offset, event)| {
+                let delay = offset.saturating_sub(previous);
+                previous = offset;
+                (delay, event)
+            })
+            .collect();
+        ScriptedSource { script }
+    }
+
+    // 依次敲击若干按键（测试使用），每次按下后隔 hold 松开，两次敲击之间隔 interval
+    #[cfg(test)]
+    pub fn typing(keys: &[Key], interval: Duration, hold: Duration) -> Self {
+        let mut script = Vec::new();
+        for key in keys {
+            script.push((interval, Event::new(EventType::KeyPress(key.clone()))));
+            script.push((hold, Event::new(EventType::KeyRelease(key.clone()))));
+        }
+        ScriptedSource { script }
+    }
+}
+
+impl InputSource for ScriptedSource {
+    fn name(&self) -> &str {
+        "scripted"
+    }
+
+    // 发出时间以实际发出的时刻为准，脚本中事件原有的时间会被替换
+    fn run(self: Box<Self>, sink: EventSink, stop: StopSignal) -> Result<(), Box<dyn std::error::Error>> {
+        info!("开始回放 {} 个脚本事件", self.script.len());
+        for (delay, mut event) in self.script {
+            if stop.wait_timeout(delay) {
+                debug!("脚本回放被停止");
+                return Ok(());
+            }
+            event.time = Instant::now();
+            sink.emit(event);
+        }
+        info!("脚本事件回放完毕");
+        Ok(())
+    }
+}
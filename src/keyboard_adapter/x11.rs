@@ -0,0 +1,361 @@
+// X11 键盘和鼠标监听 - 通过 XRecord 扩展捕获全局按键和鼠标按钮，不需要 /dev/input 权限
+use log::{debug, error, info, warn};
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::record::{self, ConnectionExt as _};
+use x11rb::protocol::xproto;
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::listener::{EventSink, StopSignal};
+use super::InputSource;
+use super::modifiers::{flags_to_modifiers, X11_MODIFIER_MASKS};
+use super::{evdev_code_to_key, Button, Event, EventType, Key, Modifiers};
+
+// XRecord 回复的类别（协议中定义，x11rb 未导出常量）
+const RECORD_FROM_SERVER: u8 = 0;
+const RECORD_START_OF_DATA: u8 = 4;
+const RECORD_END_OF_DATA: u8 = 5;
+
+// 核心协议事件固定为 32 字节
+const X_EVENT_SIZE: usize = 32;
+
+// XKB 的 evdev 规则中，X 键码 = evdev 键码 + 8
+const X_KEYCODE_OFFSET: u8 = 8;
+
+pub fn keycode_to_key(keycode: u8) -> Key {
+    match keycode.checked_sub(X_KEYCODE_OFFSET) {
+        Some(code) => evdev_code_to_key(code as u16),
+        None => Key::Unknown(keycode as u32),
+    }
+}
+
+// 核心协议的按钮编号：1-3 为左中右键，4-7 为滚轮上下左右，8、9 为后退、前进侧键
+fn button_event_type(button: u8, pressed: bool) -> Option<EventType> {
+    let button = match button {
+        1 => Button::Left,
+        2 => Button::Middle,
+        3 => Button::Right,
+        // 滚轮每滚动一格产生一对按下+松开，只在按下时上报
+        4..=7 if !pressed => return None,
+        4 => return Some(EventType::Wheel { delta_x: 0, delta_y: 1 }),
+        5 => return Some(EventType::Wheel { delta_x: 0, delta_y: -1 }),
+        6 => return Some(EventType::Wheel { delta_x: -1, delta_y: 0 }),
+        7 => return Some(EventType::Wheel { delta_x: 1, delta_y: 0 }),
+        8 => Button::Back,
+        9 => Button::Forward,
+        other => Button::Unknown(other as u32),
+    };
+    Some(if pressed { EventType::ButtonPress(button) } else { EventType::ButtonRelease(button) })
+}
+
+// XRecord 设备事件解码器。核心事件不带自动重复标记，需要记住按住的键：
+// 已按住的键再次按下，或松开后紧接着以相同服务器时间按下，都是自动重复
+pub struct RecordDecoder {
+    held: HashSet<u8>,
+    last_release: Option<(u8, u32)>,
+}
+
+impl RecordDecoder {
+    pub fn new() -> Self {
+        RecordDecoder {
+            held: HashSet::new(),
+            last_release: None,
+        }
+    }
+
+    // 解析 XRecord 截获的设备事件数据（若干条 32 字节的核心事件）
+    pub fn decode(&mut self, data: &[u8]) -> Vec<Event> {
+        let time = Instant::now();
+        let mut events = Vec::new();
+        for raw in data.chunks_exact(X_EVENT_SIZE) {
+            let keycode = raw[1];
+            let server_time = u32::from_ne_bytes([raw[4], raw[5], raw[6], raw[7]]);
+            // state 是事件发生前的修饰键状态
+            let state = u16::from_ne_bytes([raw[28], raw[29]]);
+            let key = keycode_to_key(keycode);
+
+            // 最高位表示事件由 SendEvent 产生
+            let (event_type, is_repeat) = match raw[0] & 0x7F {
+                xproto::KEY_PRESS_EVENT => {
+                    let is_repeat = !self.held.insert(keycode)
+                        || self.last_release == Some((keycode, server_time));
+                    (EventType::KeyPress(key.clone()), is_repeat)
+                }
+                xproto::KEY_RELEASE_EVENT => {
+                    self.held.remove(&keycode);
+                    self.last_release = Some((keycode, server_time));
+                    (EventType::KeyRelease(key.clone()), false)
+                }
+                // 按钮事件的 detail 字段是按钮编号而不是键码
+                xproto::BUTTON_PRESS_EVENT => match button_event_type(keycode, true) {
+                    Some(event_type) => (event_type, false),
+                    None => continue,
+                },
+                xproto::BUTTON_RELEASE_EVENT => match button_event_type(keycode, false) {
+                    Some(event_type) => (event_type, false),
+                    None => continue,
+                },
+                _ => continue,
+            };
+
+            let mut modifiers = flags_to_modifiers(state as u64, X11_MODIFIER_MASKS);
+            match (&event_type, key.modifier()) {
+                (_, Some(modifier)) if modifier == Modifiers::CAPS_LOCK => {}
+                (EventType::KeyPress(_), Some(modifier)) => modifiers.insert(modifier),
+                (EventType::KeyRelease(_), Some(modifier)) => modifiers.remove(modifier),
+                _ => {}
+            }
+
+            events.push(Event {
+                event_type,
+                time,
+                is_repeat,
+                modifiers,
+                device: 0,
+            });
+        }
+        events
+    }
+}
+
+// 连接指定的 X 显示（None 表示使用 DISPLAY 环境变量），
+// 便于在测试中连接到本地 Xvfb 并用 XTest 注入按键
+pub fn listen_display(display: Option<&str>, sink: EventSink, stop: StopSignal) -> Result<(), Box<dyn std::error::Error>> {
+    info!("🎯 启动X11键盘监听 (XRecord)");
+
+    // XRecord 需要两个连接：一个用于控制，一个用于接收数据
+    let (ctrl_conn, _) = x11rb::connect(display)
+        .map_err(|e| format!("无法连接X服务器: {:?}", e))?;
+    let (data_conn, _) = x11rb::connect(display)
+        .map_err(|e| format!("无法连接X服务器: {:?}", e))?;
+
+    if ctrl_conn.extension_information(record::X11_EXTENSION_NAME)?.is_none() {
+        error!("❌ X服务器不支持RECORD扩展");
+        return Err("X服务器不支持RECORD扩展".into());
+    }
+
+    let context = ctrl_conn.generate_id()?;
+    let range = record::Range {
+        device_events: record::Range8 {
+            first: xproto::KEY_PRESS_EVENT,
+            last: xproto::BUTTON_RELEASE_EVENT,
+        },
+        ..Default::default()
+    };
+    ctrl_conn
+        .record_create_context(context, 0, &[record::CS::ALL_CLIENTS.into()], &[range])?
+        .check()
+        .map_err(|e| format!("创建XRecord上下文失败: {:?}", e))?;
+    info!("✅ XRecord上下文创建成功");
+
+    // 停止时通过控制连接关闭上下文，服务器随后在数据连接上发送 EndOfData
+    let ctrl_conn = Arc::new(ctrl_conn);
+    let stop_conn = Arc::clone(&ctrl_conn);
+    stop.on_stop(move || {
+        if let Ok(cookie) = stop_conn.record_disable_context(context) {
+            let _ = cookie.check();
+        }
+    });
+
+    let mut decoder = RecordDecoder::new();
+    for reply in data_conn.record_enable_context(context)? {
+        let reply = reply?;
+        if reply.client_swapped {
+            warn!("忽略字节序不同的客户端数据");
+            continue;
+        }
+        match reply.category {
+            RECORD_FROM_SERVER => {
+                for event in decoder.decode(&reply.data) {
+                    debug!("X11事件: {:?}", event.event_type);
+                    sink.emit(event);
+                }
+            }
+            RECORD_START_OF_DATA => info!("🎧 开始监听X11键盘事件..."),
+            RECORD_END_OF_DATA => break,
+            other => debug!("忽略XRecord回复类别: {}", other),
+        }
+    }
+
+    let _ = ctrl_conn.record_free_context(context);
+    let _ = ctrl_conn.flush();
+    info!("X11键盘监听结束");
+    Ok(())
+}
+
+// XRecord 输入源，display 为 None 时使用 DISPLAY 环境变量
+pub struct XRecordSource {
+    pub display: Option<String>,
+}
+
+impl InputSource for XRecordSource {
+    fn name(&self) -> &str {
+        "X11 XRecord"
+    }
+
+    fn run(self: Box<Self>, sink: EventSink, stop: StopSignal) -> Result<(), Box<dyn std::error::Error>> {
+        listen_display(self.display.as_deref(), sink, stop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard_adapter::ListenerHandle;
+
+    use std::process::{Child, Command};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    // X 键码 38 为 evdev 30（A 键），50 为左Shift
+    const X_KEY_A: u8 = 38;
+    const X_KEY_SHIFT_L: u8 = 50;
+
+    // 构造一条 32 字节的核心事件：type、detail、服务器时间、state
+    fn core_event(kind: u8, detail: u8, server_time: u32, state: u16) -> Vec<u8> {
+        let mut raw = vec![0u8; X_EVENT_SIZE];
+        raw[0] = kind;
+        raw[1] = detail;
+        raw[4..8].copy_from_slice(&server_time.to_ne_bytes());
+        raw[28..30].copy_from_slice(&state.to_ne_bytes());
+        raw
+    }
+
+    fn decoded(events: &[Event]) -> Vec<(String, bool)> {
+        events.iter().map(|event| (format!("{:?}", event.event_type), event.is_repeat)).collect()
+    }
+
+    #[test]
+    fn decodes_key_press_and_release() {
+        let mut decoder = RecordDecoder::new();
+        let data = [
+            core_event(xproto::KEY_PRESS_EVENT, X_KEY_A, 100, 0),
+            core_event(xproto::KEY_RELEASE_EVENT, X_KEY_A, 180, 0),
+        ].concat();
+        assert_eq!(decoded(&decoder.decode(&data)), vec![
+            ("KeyPress(KeyA)".to_string(), false),
+            ("KeyRelease(KeyA)".to_string(), false),
+        ]);
+    }
+
+    #[test]
+    fn detects_auto_repeat() {
+        let mut decoder = RecordDecoder::new();
+        // 自动重复：服务器发出与松开时间相同的按下，或者没有松开就再次按下
+        let data = [
+            core_event(xproto::KEY_PRESS_EVENT, X_KEY_A, 100, 0),
+            core_event(xproto::KEY_RELEASE_EVENT, X_KEY_A, 600, 0),
+            core_event(xproto::KEY_PRESS_EVENT, X_KEY_A, 600, 0),
+            core_event(xproto::KEY_PRESS_EVENT, X_KEY_A, 630, 0),
+            core_event(xproto::KEY_RELEASE_EVENT, X_KEY_A, 700, 0),
+            core_event(xproto::KEY_PRESS_EVENT, X_KEY_A, 900, 0),
+        ].concat();
+        let repeats: Vec<bool> = decoder.decode(&data).iter()
+            .filter(|event| matches!(event.event_type, EventType::KeyPress(_)))
+            .map(|event| event.is_repeat)
+            .collect();
+        assert_eq!(repeats, vec![false, true, true, false]);
+    }
+
+    #[test]
+    fn applies_modifier_state() {
+        let mut decoder = RecordDecoder::new();
+        let data = [
+            core_event(xproto::KEY_PRESS_EVENT, X_KEY_SHIFT_L, 100, 0),
+            core_event(xproto::KEY_PRESS_EVENT, X_KEY_A, 120, 0x0001),
+            core_event(xproto::KEY_RELEASE_EVENT, X_KEY_SHIFT_L, 140, 0x0001),
+        ].concat();
+        let events = decoder.decode(&data);
+        assert!(events[0].modifiers.contains(Modifiers::SHIFT));
+        assert!(events[1].modifiers.contains(Modifiers::SHIFT));
+        assert_eq!(events[2].modifiers, Modifiers::empty());
+    }
+
+    #[test]
+    fn decodes_buttons_and_wheel() {
+        let mut decoder = RecordDecoder::new();
+        let data = [
+            core_event(xproto::BUTTON_PRESS_EVENT, 1, 100, 0),
+            core_event(xproto::BUTTON_RELEASE_EVENT, 1, 150, 0),
+            core_event(xproto::BUTTON_PRESS_EVENT, 4, 200, 0),
+            core_event(xproto::BUTTON_RELEASE_EVENT, 4, 200, 0),
+            core_event(xproto::BUTTON_PRESS_EVENT, 8, 300, 0),
+            // SendEvent 产生的事件最高位为 1，同样解码
+            core_event(xproto::KEY_PRESS_EVENT | 0x80, X_KEY_A, 400, 0),
+        ].concat();
+        assert_eq!(decoded(&decoder.decode(&data)), vec![
+            ("ButtonPress(Left)".to_string(), false),
+            ("ButtonRelease(Left)".to_string(), false),
+            ("Wheel { delta_x: 0, delta_y: 1 }".to_string(), false),
+            ("ButtonPress(Back)".to_string(), false),
+            ("KeyPress(KeyA)".to_string(), false),
+        ]);
+    }
+
+    // 本地 Xvfb，测试结束时关闭
+    struct Xvfb(Child);
+
+    impl Drop for Xvfb {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    // 需要安装 Xvfb：cargo test -- --ignored xvfb
+    #[test]
+    #[ignore]
+    fn xvfb_records_xtest_keys() {
+        use x11rb::protocol::xtest::ConnectionExt as _;
+        use x11rb::wrapper::ConnectionExt as _;
+
+        let display = ":97";
+        let _xvfb = Xvfb(Command::new("Xvfb").args([display, "-nolisten", "tcp"]).spawn().expect("无法启动 Xvfb"));
+        let conn = (0..50)
+            .find_map(|_| {
+                std::thread::sleep(Duration::from_millis(100));
+                x11rb::connect(Some(display)).ok()
+            })
+            .map(|(conn, _)| conn)
+            .expect("无法连接 Xvfb");
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_by_listener = Arc::clone(&received);
+        let handle = ListenerHandle::spawn(
+            "xvfb-test",
+            move |event| received_by_listener.lock().unwrap().push(event),
+            move |sink, stop| listen_display(Some(display), sink, stop),
+        ).unwrap();
+        // 等待 XRecord 上下文启用
+        std::thread::sleep(Duration::from_millis(500));
+
+        let root = conn.setup().roots[0].root;
+        for (kind, detail) in [
+            (xproto::KEY_PRESS_EVENT, X_KEY_A),
+            (xproto::KEY_RELEASE_EVENT, X_KEY_A),
+            (xproto::BUTTON_PRESS_EVENT, 1),
+            (xproto::BUTTON_RELEASE_EVENT, 1),
+        ] {
+            conn.xtest_fake_input(kind, detail, x11rb::CURRENT_TIME, root, 0, 0, 0).unwrap();
+        }
+        conn.sync().unwrap();
+
+        for _ in 0..50 {
+            if received.lock().unwrap().len() >= 4 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        handle.stop();
+        handle.join().unwrap();
+
+        assert_eq!(decoded(&received.lock().unwrap()), vec![
+            ("KeyPress(KeyA)".to_string(), false),
+            ("KeyRelease(KeyA)".to_string(), false),
+            ("ButtonPress(Left)".to_string(), false),
+            ("ButtonRelease(Left)".to_string(), false),
+        ]);
+    }
+}
@@ -0,0 +1,112 @@
+// 响度分析 - 测量音效的整体响度并计算归一化增益，让音量滑块对每个音效的含义相同
+use crate::audio_engine::{Sample, OUTPUT_CHANNELS, OUTPUT_SAMPLE_RATE};
+
+// 归一化后的目标响度（dBFS RMS）
+pub const TARGET_DB: f32 = -20.0;
+// 归一化增益的范围，避免几乎无声的样本被放大成噪声
+const MIN_GAIN_DB: f32 = -20.0;
+const MAX_GAIN_DB: f32 = 12.0;
+
+// 按 10 ms 分块计算均方值。按键音很短，用比 EBU R128 的 400 ms 更短的块
+const BLOCK_MS: u32 = 10;
+// 门限与 EBU R128 相同的思路：先去掉静音块，再去掉比平均响度低很多的尾音块
+const ABSOLUTE_GATE_DB: f32 = -70.0;
+const RELATIVE_GATE_DB: f32 = -20.0;
+
+fn to_db(power: f64) -> f32 {
+    (10.0 * power.log10()) as f32
+}
+
+// 测量一组样本（单个文件，或音效包中的全部样本）的整体响度，单位 dBFS RMS；全部静音时返回None
+pub fn measure(samples: &[&Sample]) -> Option<f32> {
+    let block = (OUTPUT_SAMPLE_RATE * BLOCK_MS / 1000 * OUTPUT_CHANNELS as u32) as usize;
+    let mut powers = Vec::new();
+    for sample in samples {
+        for chunk in sample.data.chunks(block) {
+            let power = chunk.iter().map(|value| (*value as f64) * (*value as f64)).sum::<f64>() / chunk.len() as f64;
+            if power > 0.0 && to_db(power) > ABSOLUTE_GATE_DB {
+                powers.push(power);
+            }
+        }
+    }
+    if powers.is_empty() {
+        return None;
+    }
+
+    let mean = powers.iter().sum::<f64>() / powers.len() as f64;
+    let gate = to_db(mean) + RELATIVE_GATE_DB;
+    let gated: Vec<f64> = powers.into_iter().filter(|power| to_db(*power) > gate).collect();
+    Some(to_db(gated.iter().sum::<f64>() / gated.len() as f64))
+}
+
+// 把响度调整到 TARGET_DB 所需的增益（线性倍数）
+pub fn normalization_gain(loudness_db: f32) -> f32 {
+    let gain_db = (TARGET_DB - loudness_db).clamp(MIN_GAIN_DB, MAX_GAIN_DB);
+    10f32.powf(gain_db / 20.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 两个声道相同的立体声样本，每个声道 frames 帧
+    fn stereo(frames: usize, value: impl Fn(usize) -> f32) -> Sample {
+        Sample { data: (0..frames).flat_map(|frame| [value(frame); 2]).collect() }
+    }
+
+    fn sine(amplitude: f32, frames: usize) -> Sample {
+        stereo(frames, |frame| amplitude * (2.0 * std::f32::consts::PI * 441.0 * frame as f32 / OUTPUT_SAMPLE_RATE as f32).sin())
+    }
+
+    fn square(amplitude: f32, frames: usize) -> Sample {
+        stereo(frames, |frame| if frame / 50 % 2 == 0 { amplitude } else { -amplitude })
+    }
+
+    fn assert_db(actual: Option<f32>, expected: f32) {
+        let actual = actual.expect("应当有响度");
+        assert!((actual - expected).abs() < 0.05, "响度 {} dB，期望 {} dB", actual, expected);
+    }
+
+    #[test]
+    fn silence_has_no_loudness() {
+        assert_eq!(measure(&[&stereo(4410, |_| 0.0)]), None);
+        assert_eq!(measure(&[]), None);
+        // 低于绝对门限的底噪也按静音处理
+        assert_eq!(measure(&[&square(1e-4, 4410)]), None);
+    }
+
+    #[test]
+    fn known_signals_have_known_loudness() {
+        // 满幅方波的 RMS 为 0 dBFS，正弦波比峰值低 3 dB
+        assert_db(measure(&[&square(1.0, 4410)]), 0.0);
+        assert_db(measure(&[&sine(1.0, 4410)]), -3.01);
+        assert_db(measure(&[&sine(0.1, 4410)]), -23.01);
+    }
+
+    #[test]
+    fn quiet_tail_is_gated_out() {
+        // 响亮的起音后跟一段低 40 dB 的长尾音，尾音不拉低整体响度
+        let mut sample = square(0.5, 441);
+        sample.data.extend(square(0.005, 44_100).data);
+        assert_db(measure(&[&sample]), -6.02);
+    }
+
+    #[test]
+    fn measures_all_samples_of_a_pack_together() {
+        let loud = square(1.0, 4410);
+        let quiet = square(0.5, 4410);
+        let together = measure(&[&loud, &quiet]).unwrap();
+        assert!(together < 0.0 && together > -6.02);
+    }
+
+    #[test]
+    fn gain_reaches_target_level() {
+        assert!((normalization_gain(TARGET_DB) - 1.0).abs() < 1e-6);
+        // 响度 -26 dB 的音效需要放大 6 dB 才到 -20 dB
+        assert!((normalization_gain(-26.0) - 10f32.powf(6.0 / 20.0)).abs() < 1e-5);
+        assert!((normalization_gain(-14.0) - 10f32.powf(-6.0 / 20.0)).abs() < 1e-5);
+        // 放大和衰减都有上限
+        assert!((normalization_gain(-80.0) - 10f32.powf(MAX_GAIN_DB / 20.0)).abs() < 1e-5);
+        assert!((normalization_gain(40.0) - 10f32.powf(MIN_GAIN_DB / 20.0)).abs() < 1e-6);
+    }
+}
@@ -1,30 +1,89 @@
-use rodio::{Decoder, OutputStream, Sink};
 use serde::{Deserialize, Serialize};
 
+// 引入常驻音频引擎
+mod audio_engine;
+use audio_engine::{AudioEngine, PolyphonySettings, SampleRef, VoiceParams};
+
+// 引入音效包（Mechvibes、bucklespring 等多样本格式）
+mod sound_pack;
+use sound_pack::{SoundPack, SoundSource};
+
 // 引入我们的键盘适配器
 mod keyboard_adapter;
-use keyboard_adapter::{listen, EventType};
+use keyboard_adapter::{listen, listen_with, Button, Event, EventType, Key, KeyClass, Layout, ScriptedSource};
+
+// 引入按键会话录制与回放
+mod session;
+use session::SessionRecorder;
+
+// 引入离线渲染
+mod render;
+
+// 引入响度分析
+mod loudness;
+
+// 引入静音裁剪
+mod trim;
+use trim::TrimSettings;
+
+// 引入按键自动重复处理
+mod repeat;
+use repeat::{RepeatDecision, RepeatFilter, RepeatSettings};
+
+// 引入内置合成器
+mod synth;
+use synth::SynthSettings;
+
+// 引入音效变化（随机变调、音量抖动、多样本轮换）
+mod variation;
+use variation::{Variation, Varier};
 
 // 引入原生菜单
+#[cfg(target_os = "macos")]
 mod native_menu;
+#[cfg(target_os = "macos")]
 use native_menu::NativeMenu;
 
 
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::fs::File;
-use std::io::BufReader;
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::thread;
+use std::time::Duration;
 // 暂时移除pixels依赖，使用简化的滑动条实现
 use chrono::Local;
 use log::{debug, error, info, warn};
 use simplelog::*;
 
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
 struct Settings {
     sound_enabled: bool,
     volume: f32, // 音量范围 0.0 - 1.0
-    current_sound: String, // 当前选择的声音文件名
+    current_sound: String, // 当前选择的声音文件名，也是未单独配置按键的后备音效
+    key_sounds: HashMap<String, String>, // 单个按键 -> 声音文件名，如 "Space" -> "咕嘟.mp3"
+    class_sounds: HashMap<KeyClass, String>, // 按键类别 -> 声音文件名，如 "enter" -> "通过.mp3"
+    release_sounds: HashMap<String, String>, // 按下音效 -> 松开音效，未配置则松开时不发声
+    keyboard_sounds_enabled: bool, // 三种声源可以分别开关
+    click_sounds_enabled: bool,
+    scroll_sounds_enabled: bool,
+    click_sound: Option<String>, // 鼠标点击音效，未配置时使用当前音效
+    button_sounds: HashMap<String, String>, // 单个鼠标按钮 -> 声音文件名，如 "Right" -> "魔法.mp3"
+    scroll_sound: Option<String>, // 滚轮音效，未配置时使用当前音效
+    variations: HashMap<String, Variation>, // 声音文件名 -> 变化设置，轮换池中的音效使用池主人的设置
+    random_seed: u64, // 变化使用的随机种子，相同种子下同一会话的离线渲染结果完全一致
+    keyboard_layout: Layout, // 实体键盘布局，"ansi" 或 "iso"
+    pan_width: f32, // 按键位置声像的宽度 0.0 - 1.0，0 为单声道居中
+    synth_sounds: HashMap<String, SynthSettings>, // 自定义合成音效，同名时覆盖内置的 "合成-click" 等音效
+    output_device: Option<String>, // 音频输出设备名称，未设置时使用系统默认设备
+    normalize_loudness: bool, // 按分析的响度把各音效调整到相同响度
+    unnormalized_sounds: Vec<String>, // 不做响度归一化的声音文件名
+    silence_trim: TrimSettings, // 加载样本时裁剪开头和结尾的静音
+    polyphony: PolyphonySettings, // 同时播放的声部上限和抢占策略
+    key_repeat: RepeatSettings, // 按住按键时自动重复的按下事件如何发声
 }
 
 impl Default for Settings {
@@ -33,20 +92,97 @@ impl Default for Settings {
             sound_enabled: true,
             volume: 0.7, // 默认音量70%
             current_sound: "sound.wav".to_string(), // 默认音效
+            key_sounds: HashMap::new(),
+            class_sounds: HashMap::new(),
+            release_sounds: HashMap::new(),
+            keyboard_sounds_enabled: true,
+            click_sounds_enabled: false,
+            scroll_sounds_enabled: false,
+            click_sound: None,
+            button_sounds: HashMap::new(),
+            scroll_sound: None,
+            variations: HashMap::new(),
+            random_seed: 0,
+            keyboard_layout: Layout::Ansi,
+            pan_width: 0.0,
+            synth_sounds: HashMap::new(),
+            output_device: None,
+            normalize_loudness: true,
+            unnormalized_sounds: Vec::new(),
+            silence_trim: TrimSettings::default(),
+            polyphony: PolyphonySettings::default(),
+            key_repeat: RepeatSettings::default(),
         }
     }
 }
 
+impl Settings {
+    // 按 单个按键 -> 按键类别 -> 当前音效 的顺序查找要播放的声音
+    fn sound_for_key(&self, key: &Key) -> &str {
+        self.key_sounds
+            .get(&key.name())
+            .or_else(|| self.class_sounds.get(&key.class()))
+            .unwrap_or(&self.current_sound)
+    }
+
+    // 松开音效跟随按下音效所属的音效组
+    fn release_sound_for_key(&self, key: &Key) -> Option<&str> {
+        self.release_sounds.get(self.sound_for_key(key)).map(String::as_str)
+    }
+
+    // 按 单个按钮 -> 点击音效 -> 当前音效 的顺序查找
+    fn sound_for_button(&self, button: &Button) -> &str {
+        self.button_sounds
+            .get(&button.name())
+            .or(self.click_sound.as_ref())
+            .unwrap_or(&self.current_sound)
+    }
+
+    fn release_sound_for_button(&self, button: &Button) -> Option<&str> {
+        self.release_sounds.get(self.sound_for_button(button)).map(String::as_str)
+    }
+
+    fn scroll_sound(&self) -> &str {
+        self.scroll_sound.as_ref().unwrap_or(&self.current_sound)
+    }
+
+    // 事件所属的声源（键盘、鼠标点击、滚轮）是否开启
+    fn source_enabled(&self, event_type: &EventType) -> bool {
+        match event_type {
+            EventType::KeyPress(_) | EventType::KeyRelease(_) => self.keyboard_sounds_enabled,
+            EventType::ButtonPress(_) | EventType::ButtonRelease(_) => self.click_sounds_enabled,
+            EventType::Wheel { .. } => self.scroll_sounds_enabled,
+        }
+    }
+}
+
+// 一次要播放的样本：sound_name 为设置中的音效名（用于查找变化设置），
+// source_name 为轮换后实际使用的音效名（用于响度归一化）
+struct Picked {
+    sound_name: String,
+    source_name: String,
+    sample_ref: SampleRef,
+}
+
 struct AppState {
     settings: Arc<Mutex<Settings>>,
-    pub sound_files: Vec<(String, PathBuf)>, // (显示名称, 文件路径) 对
+    pub sound_files: Vec<(String, SoundSource)>, // (显示名称, 音频文件或音效包) 对
+    audio: AudioEngine,
+    varier: Varier,
+    loudness: Mutex<HashMap<String, Option<f32>>>, // 音效名 -> 分析得到的响度，静音为 None
+    repeat_filter: RepeatFilter,
 }
 
 fn init_logging() -> Result<(), Box<dyn std::error::Error>> {
     // 创建日志目录
+    #[cfg(target_os = "macos")]
     let log_dir = dirs::home_dir()
         .ok_or("无法获取用户主目录")?
         .join("Library/Logs/macos-key-sound");
+    #[cfg(not(target_os = "macos"))]
+    let log_dir = dirs::data_local_dir()
+        .ok_or("无法获取本地数据目录")?
+        .join("macos-key-sound/logs");
     
     std::fs::create_dir_all(&log_dir)?;
     
@@ -72,27 +208,69 @@ fn init_logging() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 impl AppState {
-    fn new() -> Result<Self, Box<dyn std::error::Error>> {
+    // make_audio 根据加载的设置创建音频引擎（离线渲染时不打开声卡）
+    fn new(make_audio: impl FnOnce(&Settings) -> AudioEngine) -> Result<Self, Box<dyn std::error::Error>> {
         let loaded_settings = load_settings();
         info!("加载的设置: sound_enabled = {}, volume = {:.0}%, current_sound = {}",
               loaded_settings.sound_enabled, loaded_settings.volume * 100.0, loaded_settings.current_sound);
-        let settings = Arc::new(Mutex::new(loaded_settings));
         let sound_files = locate_sound_files();
         if sound_files.is_empty() {
             warn!("未找到任何音频文件，请检查assets文件夹");
         } else {
             info!("找到 {} 个音频文件", sound_files.len());
-            for (name, path) in &sound_files {
-                info!("  - {}: {}", name, path.display());
+        }
+        Ok(Self::with_sounds(loaded_settings, sound_files, make_audio))
+    }
+
+    // 使用给定的设置和音频文件创建，不读取设置文件也不扫描 assets
+    fn with_sounds(
+        mut loaded_settings: Settings,
+        mut sound_files: Vec<(String, SoundSource)>,
+        make_audio: impl FnOnce(&Settings) -> AudioEngine,
+    ) -> Self {
+        // 内置合成音效总是可选，没有任何音频文件时也能发声
+        for (name, voicing) in synth::synth_sounds(&loaded_settings.synth_sounds) {
+            sound_files.push((name, SoundSource::Synth(voicing)));
+        }
+        for (name, source) in &sound_files {
+            info!("  - {}: {}", name, source.location());
+        }
+        if !sound_files.iter().any(|(name, _)| name == &loaded_settings.current_sound) {
+            if let Some((name, _)) = sound_files.first() {
+                warn!("当前音效 {} 不存在，改用 {}", loaded_settings.current_sound, name);
+                loaded_settings.current_sound = name.clone();
             }
         }
-        Ok(AppState { settings, sound_files })
+        let varier = Varier::new(loaded_settings.random_seed);
+        let audio = make_audio(&loaded_settings)
+            .with_trim(loaded_settings.silence_trim.clone())
+            .with_polyphony(loaded_settings.polyphony.clone());
+        let settings = Arc::new(Mutex::new(loaded_settings));
+        let app_state = AppState { settings, sound_files, audio, varier, loudness: Mutex::new(HashMap::new()), repeat_filter: RepeatFilter::default() };
+        // 预先解码当前音效和按键映射的音效，第一次按键无需等待解码
+        let mut preload_sounds = vec![app_state.get_current_sound()];
+        {
+            let settings = app_state.settings.lock().unwrap();
+            preload_sounds.extend(settings.key_sounds.values()
+                .chain(settings.class_sounds.values())
+                .chain(settings.release_sounds.values())
+                .chain(settings.button_sounds.values())
+                .chain(settings.click_sound.iter())
+                .chain(settings.scroll_sound.iter())
+                .chain(settings.key_repeat.repeat_sound.iter())
+                .cloned());
+        }
+        for sound_name in preload_sounds {
+            app_state.preload_sound(&sound_name);
+        }
+        app_state
     }
     
     fn is_sound_enabled(&self) -> bool {
         self.settings.lock().unwrap().sound_enabled
     }
     
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     fn toggle_sound(&self) -> bool {
         let mut settings = self.settings.lock().unwrap();
         settings.sound_enabled = !settings.sound_enabled;
@@ -102,10 +280,42 @@ impl AppState {
         enabled
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     fn get_volume(&self) -> f32 {
         self.settings.lock().unwrap().volume
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    fn is_click_sound_enabled(&self) -> bool {
+        self.settings.lock().unwrap().click_sounds_enabled
+    }
+
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    fn toggle_click_sound(&self) -> bool {
+        let mut settings = self.settings.lock().unwrap();
+        settings.click_sounds_enabled = !settings.click_sounds_enabled;
+        let enabled = settings.click_sounds_enabled;
+        save_settings(&settings);
+        info!("鼠标点击音效切换: {}", if enabled { "开启" } else { "关闭" });
+        enabled
+    }
+
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    fn is_scroll_sound_enabled(&self) -> bool {
+        self.settings.lock().unwrap().scroll_sounds_enabled
+    }
+
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    fn toggle_scroll_sound(&self) -> bool {
+        let mut settings = self.settings.lock().unwrap();
+        settings.scroll_sounds_enabled = !settings.scroll_sounds_enabled;
+        let enabled = settings.scroll_sounds_enabled;
+        save_settings(&settings);
+        info!("滚轮音效切换: {}", if enabled { "开启" } else { "关闭" });
+        enabled
+    }
+
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     fn set_volume(&self, volume: f32) {
         let mut settings = self.settings.lock().unwrap();
         settings.volume = volume.clamp(0.0, 1.0);
@@ -113,6 +323,7 @@ impl AppState {
         info!("音量设置为: {:.0}%", settings.volume * 100.0);
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     fn increase_volume(&self) -> f32 {
         let mut settings = self.settings.lock().unwrap();
         settings.volume = (settings.volume + 0.1).clamp(0.0, 1.0);
@@ -122,6 +333,7 @@ impl AppState {
         new_volume
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     fn decrease_volume(&self) -> f32 {
         let mut settings = self.settings.lock().unwrap();
         settings.volume = (settings.volume - 0.1).clamp(0.0, 1.0);
@@ -131,68 +343,384 @@ impl AppState {
         new_volume
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    fn get_output_device(&self) -> Option<String> {
+        self.settings.lock().unwrap().output_device.clone()
+    }
+
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    fn set_output_device(&self, device: Option<String>) {
+        let mut settings = self.settings.lock().unwrap();
+        settings.output_device = device.clone();
+        save_settings(&settings);
+        drop(settings);
+        info!("输出设备设置为: {}", device.as_deref().unwrap_or("系统默认"));
+        self.audio.set_output_device(device);
+    }
+
     fn get_current_sound(&self) -> String {
         self.settings.lock().unwrap().current_sound.clone()
     }
 
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
     fn set_current_sound(&self, sound_name: &str) {
         let mut settings = self.settings.lock().unwrap();
         settings.current_sound = sound_name.to_string();
         save_settings(&settings);
+        drop(settings);
         info!("声音切换为: {}", sound_name);
+        self.preload_sound(sound_name);
     }
 
-    fn get_current_sound_path(&self) -> Option<PathBuf> {
-        let current_sound = self.get_current_sound();
-        self.sound_files.iter()
-            .find(|(name, _)| name == &current_sound)
-            .map(|(_, path)| path.clone())
+    fn get_sound_source(&self, sound_name: &str) -> Option<&SoundSource> {
+        self.get_sound_entry(sound_name).map(|(_, source)| source)
     }
-    
-    fn play_sound(&self) {
+
+    // 预先解码并分析响度，包括该音效轮换池中的音效，播放时只需读取缓存
+    fn preload_sound(&self, sound_name: &str) {
+        let pool = self.settings.lock().unwrap().variations.get(sound_name)
+            .map(|variation| variation.pool.clone())
+            .unwrap_or_default();
+        for name in std::iter::once(sound_name).chain(pool.iter().map(String::as_str)) {
+            if let Some(source) = self.get_sound_source(name) {
+                for sample_ref in source.all_samples() {
+                    self.audio.preload(&sample_ref);
+                }
+                self.sound_loudness(name);
+            }
+        }
+    }
+
+    // 按音效的轮换设置选出这一次实际使用的音效来源，返回 (实际使用的音效名, 音效来源)
+    fn pick_sound_source(&self, sound_name: &str) -> Option<(&str, &SoundSource)> {
+        let variation = self.settings.lock().unwrap().variations.get(sound_name).cloned();
+        let picked = match &variation {
+            Some(variation) => self.varier.pick(sound_name, variation),
+            None => sound_name,
+        };
+        let entry = match self.get_sound_entry(picked) {
+            Some(entry) => Some(entry),
+            None if picked != sound_name => {
+                warn!("音效 {} 的轮换音效 {} 不存在，使用原音效", sound_name, picked);
+                self.get_sound_entry(sound_name)
+            }
+            None => None,
+        };
+        entry.map(|(name, source)| (name.as_str(), source))
+    }
+
+    fn get_sound_entry(&self, sound_name: &str) -> Option<&(String, SoundSource)> {
+        self.sound_files.iter().find(|(name, _)| name == sound_name)
+    }
+
+    // 从选出的音效来源中取样本
+    fn pick_sample<F>(&self, sound_name: String, sample: F) -> Option<Picked>
+    where
+        F: FnOnce(&SoundSource) -> Option<SampleRef>,
+    {
+        let (source_name, source) = self.pick_sound_source(&sound_name)?;
+        Some(Picked {
+            source_name: source_name.to_string(),
+            sample_ref: sample(source)?,
+            sound_name,
+        })
+    }
+
+    // 根据按键映射选择音效，映射的音效不存在时退回当前音效
+    fn pick_sample_for_key<F>(&self, key: &Key, sample: F) -> Option<Picked>
+    where
+        F: Fn(&SoundSource) -> Option<SampleRef>,
+    {
+        let sound_name = self.settings.lock().unwrap().sound_for_key(key).to_string();
+        if self.get_sound_source(&sound_name).is_none() {
+            warn!("按键 {:?} 映射的音效 {} 不存在，使用当前音效", key, sound_name);
+            return self.pick_sample(self.get_current_sound(), sample);
+        }
+        self.pick_sample(sound_name, sample)
+    }
+
+    fn press_sample(&self, key: &Key) -> Option<Picked> {
+        let picked = self.pick_sample_for_key(key, |source| source.press_sample(key));
+        if picked.is_none() {
+            warn!("未找到当前选择的音频文件，取消播放");
+        }
+        picked
+    }
+
+    // 自动重复时的声音：优先使用配置的重复音效，未配置或不存在时使用原音效
+    fn repeat_sample(&self, key: &Key, repeat_sound: Option<String>) -> Option<Picked> {
+        match repeat_sound {
+            Some(sound_name) if self.get_sound_source(&sound_name).is_some() => {
+                self.pick_sample(sound_name, |source| source.press_sample(key))
+            }
+            Some(sound_name) => {
+                warn!("未找到重复音效 {}，使用原音效", sound_name);
+                self.press_sample(key)
+            }
+            None => self.press_sample(key),
+        }
+    }
+
+    // 松开音效：优先使用 release_sounds 中配对的文件，其次使用音效包自带的松开样本
+    fn release_sample(&self, key: &Key) -> Option<Picked> {
+        let paired_sound = self.settings.lock().unwrap().release_sound_for_key(key).map(str::to_string);
+        match paired_sound {
+            Some(sound_name) => {
+                if self.get_sound_source(&sound_name).is_none() {
+                    warn!("未找到松开音效 {}，取消播放", sound_name);
+                    return None;
+                }
+                self.pick_sample(sound_name, |source| source.press_sample(key))
+            }
+            None => self.pick_sample_for_key(key, |source| source.release_sample(key)),
+        }
+    }
+
+    // 鼠标按钮按下使用映射的音效，松开只播放 release_sounds 中配对的音效
+    fn button_sample(&self, button: &Button, pressed: bool) -> Option<Picked> {
+        let settings = self.settings.lock().unwrap();
+        let sound_name = if pressed {
+            settings.sound_for_button(button).to_string()
+        } else {
+            settings.release_sound_for_button(button)?.to_string()
+        };
+        drop(settings);
+        if self.get_sound_source(&sound_name).is_none() {
+            warn!("未找到鼠标音效 {}，取消播放", sound_name);
+            return None;
+        }
+        self.pick_sample(sound_name, SoundSource::default_sample)
+    }
+
+    fn scroll_sample(&self) -> Option<Picked> {
+        let sound_name = self.settings.lock().unwrap().scroll_sound().to_string();
+        if self.get_sound_source(&sound_name).is_none() {
+            warn!("未找到滚轮音效 {}，取消播放", sound_name);
+            return None;
+        }
+        self.pick_sample(sound_name, SoundSource::default_sample)
+    }
+
+    // 音效的整体响度（dBFS RMS），第一次查询时解码全部样本并分析，结果缓存。
+    // 分析需要解码整个音效，只在预加载和诊断时调用
+    fn sound_loudness(&self, sound_name: &str) -> Option<f32> {
+        if let Some(loudness) = self.loudness.lock().unwrap().get(sound_name) {
+            return *loudness;
+        }
+        let source = self.get_sound_source(sound_name)?;
+        let samples: Vec<_> = source.all_samples().iter().filter_map(|sample_ref| self.audio.load(sample_ref)).collect();
+        let loudness = loudness::measure(&samples.iter().map(|sample| sample.as_ref()).collect::<Vec<_>>());
+        match loudness {
+            Some(db) => info!("响度分析: {} = {:.1} dB, 归一化增益 {:+.1} dB",
+                              sound_name, db, 20.0 * loudness::normalization_gain(db).log10()),
+            None => info!("响度分析: {} 为静音，不做归一化", sound_name),
+        }
+        self.loudness.lock().unwrap().insert(sound_name.to_string(), loudness);
+        loudness
+    }
+
+    // 实际播放的音效的归一化增益，关闭归一化时为 1.0
+    fn normalization_gain(&self, source_name: &str) -> f32 {
+        {
+            let settings = self.settings.lock().unwrap();
+            if !settings.normalize_loudness || settings.unnormalized_sounds.iter().any(|name| name == source_name) {
+                return 1.0;
+            }
+        }
+        // 播放路径上只读取预加载时分析的结果，没有分析过的音效不做归一化
+        match self.loudness.lock().unwrap().get(source_name) {
+            Some(loudness) => loudness.map(loudness::normalization_gain).unwrap_or(1.0),
+            None => {
+                debug!("音效 {} 尚未分析响度，不做归一化", source_name);
+                1.0
+            }
+        }
+    }
+
+    // 根据当前设置决定事件要播放的样本和播放参数，实时播放和离线渲染共用
+    fn voice_for_event(&self, event: &Event) -> Option<(SampleRef, VoiceParams)> {
         if !self.is_sound_enabled() {
             debug!("音效已关闭，跳过播放");
-            return;
+            return None;
         }
-        let sound_path = self.get_current_sound_path();
-        if sound_path.is_none() {
-            warn!("未找到当前选择的音频文件，取消播放");
-            return;
-        }
-        let volume = self.get_volume();
-        debug!("准备播放音效: {:?}, 音量: {:.0}%", sound_path, volume * 100.0);
-        thread::spawn(move || {
-            if let Some(path) = sound_path {
-                debug!("音频线程启动，文件: {}", path.display());
-                match OutputStream::try_default() {
-                    Ok((_stream, stream_handle)) => {
-                        match Sink::try_new(&stream_handle) {
-                            Ok(sink) => {
-                                // 设置音量
-                                sink.set_volume(volume);
-                                match File::open(&path) {
-                                    Ok(file) => {
-                                        let source = BufReader::new(file);
-                                        match Decoder::new(source) {
-                                            Ok(decoder) => {
-                                                sink.append(decoder);
-                                                sink.sleep_until_end();
-                                                debug!("音效播放完成，音量: {:.0}%", volume * 100.0);
-                                            }
-                                            Err(e) => error!("音频解码失败: {:?}", e),
-                                        }
-                                    }
-                                    Err(e) => error!("无法打开音频文件 {}: {:?}", path.display(), e),
-                                }
-                            }
-                            Err(e) => error!("创建Sink失败: {:?}", e),
-                        }
+        if !self.settings.lock().unwrap().source_enabled(&event.event_type) {
+            return None;
+        }
+        let mut repeat_gain = 1.0;
+        let picked = match &event.event_type {
+            EventType::KeyPress(key) => {
+                let repeat = self.settings.lock().unwrap().key_repeat.clone();
+                match self.repeat_filter.decide(&repeat, key, event.is_repeat, event.time) {
+                    RepeatDecision::Play => self.press_sample(key),
+                    RepeatDecision::Soft => {
+                        repeat_gain = repeat.repeat_gain;
+                        self.repeat_sample(key, repeat.repeat_sound)
+                    }
+                    RepeatDecision::Skip => {
+                        debug!("跳过自动重复: {:?}", key);
+                        return None;
                     }
-                    Err(e) => error!("创建音频输出流失败: {:?}", e),
                 }
             }
-        });
+            EventType::KeyRelease(key) => self.release_sample(key),
+            EventType::ButtonPress(button) => self.button_sample(button, true),
+            EventType::ButtonRelease(button) => self.button_sample(button, false),
+            EventType::Wheel { .. } => self.scroll_sample(),
+        }?;
+        let normalization = self.normalization_gain(&picked.source_name);
+
+        let settings = self.settings.lock().unwrap();
+        let mut params = VoiceParams::new(settings.volume * normalization * repeat_gain);
+        params.group = Some(voice_group(&event.event_type));
+        if let EventType::KeyPress(key) | EventType::KeyRelease(key) = &event.event_type {
+            params.pan = key.pan(settings.keyboard_layout, settings.pan_width);
+        }
+        let params = match settings.variations.get(&picked.sound_name) {
+            Some(variation) => self.varier.apply(variation, params),
+            None => params,
+        };
+        Some((picked.sample_ref, params))
     }
+
+    // 实时监听和会话回放共用的事件处理
+    fn handle_event(&self, event: &Event) {
+        match &event.event_type {
+            EventType::KeyPress(key) => {
+                info!("按下按键: {:?} (重复: {}, 修饰键: {:?}, 设备: {})", key, event.is_repeat, event.modifiers, event.device);
+            }
+            EventType::KeyRelease(key) => {
+                debug!("松开按键: {:?}", key);
+            }
+            EventType::ButtonPress(button) => debug!("按下鼠标按钮: {:?}", button),
+            EventType::ButtonRelease(button) => debug!("松开鼠标按钮: {:?}", button),
+            EventType::Wheel { delta_x, delta_y } => debug!("滚轮滚动: x={}, y={}", delta_x, delta_y),
+        }
+        if let Some((sample_ref, params)) = self.voice_for_event(event) {
+            debug!("准备播放音效: {:?}, 音量: {:.0}%, 速率: {:.3}, 声像: {:.2}", sample_ref, params.gain * 100.0, params.rate, params.pan);
+            self.audio.play(&sample_ref, params);
+        }
+    }
+}
+
+// 同一个按键（或鼠标按钮）的按下声和松开声各为一个声部组，滚轮为一组
+fn voice_group(event_type: &EventType) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    match event_type {
+        EventType::KeyPress(key) => (0u8, key).hash(&mut hasher),
+        EventType::KeyRelease(key) => (1u8, key).hash(&mut hasher),
+        EventType::ButtonPress(button) => (2u8, button).hash(&mut hasher),
+        EventType::ButtonRelease(button) => (3u8, button).hash(&mut hasher),
+        EventType::Wheel { .. } => 4u8.hash(&mut hasher),
+    }
+    hasher.finish()
+}
+
+fn voice_stats_summary(audio: &AudioEngine) -> String {
+    let stats = audio.voice_stats();
+    format!("声部统计: 抢占 {} 个, 丢弃 {} 个", stats.stolen, stats.dropped)
+}
+
+// 退出和回放、渲染结束时在日志中输出声部统计
+fn log_voice_stats(audio: &AudioEngine) {
+    info!("{}", voice_stats_summary(audio));
+}
+
+// 回放结束后等待最后的音效播放完再退出
+const REPLAY_TAIL: Duration = Duration::from_millis(1500);
+
+// 命令行参数决定的运行方式
+#[derive(Debug, PartialEq)]
+enum RunMode {
+    // 实时监听键盘，可以同时把按键录制到文件
+    Live { record: Option<PathBuf> },
+    // 回放录制的会话文件，不需要键盘权限
+    Replay(PathBuf),
+    // 把会话文件渲染为 WAV 文件，不需要声卡
+    Render { session: PathBuf, output: PathBuf },
+    // 列出可用的音频输出设备
+    ListDevices,
+    // 解码全部音效并输出诊断信息
+    Diagnose,
+}
+
+// args 不包括程序名。Finder 启动的 .app 会带上 -psn_0_12345 这样的进程序列号参数，直接忽略；
+// 其他不认识的参数只警告，照常实时监听，参数缺失等错误才返回 Err
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<RunMode, String> {
+    let mut record = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => {
+                let path = args.next().ok_or("--record 需要指定文件路径")?;
+                record = Some(PathBuf::from(path));
+            }
+            "--replay" => {
+                let path = args.next().ok_or("--replay 需要指定文件路径")?;
+                return Ok(RunMode::Replay(PathBuf::from(path)));
+            }
+            "--render" => {
+                let session = args.next().ok_or("--render 需要指定会话文件和输出文件路径")?;
+                let output = args.next().ok_or("--render 需要指定输出 WAV 文件路径")?;
+                return Ok(RunMode::Render { session: PathBuf::from(session), output: PathBuf::from(output) });
+            }
+            "--list-devices" => return Ok(RunMode::ListDevices),
+            "--diagnose" => return Ok(RunMode::Diagnose),
+            other if other.starts_with("-psn_") => debug!("忽略进程序列号参数: {}", other),
+            other => warn!("忽略未知参数: {}（可用参数: --record <文件>, --replay <文件>, --render <会话文件> <输出.wav>, --list-devices, --diagnose）", other),
+        }
+    }
+    Ok(RunMode::Live { record })
+}
+
+// 回放会话：事件经过与实时监听相同的处理流程，播放完毕后退出
+fn run_replay(app_state: Arc<AppState>, path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    let timeline = session::load_session(path)?;
+    let source = ScriptedSource::from_timeline(timeline);
+    let app_state_for_events = Arc::clone(&app_state);
+    let listener = listen_with(Box::new(source), move |event| app_state_for_events.handle_event(&event))?;
+    listener.join()?;
+
+    thread::sleep(REPLAY_TAIL);
+    log_voice_stats(&app_state.audio);
+    info!("会话回放结束");
+    Ok(())
+}
+
+// 离线渲染会话：按当前设置选择音效，按事件时间精确混音后写入 WAV 文件
+fn run_render(app_state: &AppState, session_path: &std::path::Path, output: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    let timeline = session::load_session(session_path)?;
+    let samples = render::render_session(&app_state.audio, &timeline, |event| app_state.voice_for_event(event));
+    log_voice_stats(&app_state.audio);
+    render::write_wav(output, &samples)?;
+    Ok(())
+}
+
+// 诊断输出：逐个音效解码全部样本，列出时长、裁掉的静音和响度，最后输出复音数设置和声部统计
+fn run_diagnose(app_state: &AppState) {
+    for (name, source) in &app_state.sound_files {
+        match app_state.sound_loudness(name) {
+            Some(db) => println!("{} ({}): 响度 {:.1} dB", name, source.location(), db),
+            None => println!("{} ({}): 静音或无法解码", name, source.location()),
+        }
+        for sample_ref in source.all_samples() {
+            let Some(sample) = app_state.audio.load(&sample_ref) else {
+                println!("  {}: 无法解码", sample_ref);
+                continue;
+            };
+            let trimmed = app_state.audio.trimmed(&sample_ref).unwrap_or_default();
+            println!("  {}: {:.1} ms, 裁剪开头 {:.1} ms, 结尾 {:.1} ms",
+                     sample_ref,
+                     sample.duration().as_secs_f64() * 1000.0,
+                     trimmed.leading.as_secs_f64() * 1000.0,
+                     trimmed.trailing.as_secs_f64() * 1000.0);
+        }
+    }
+
+    let polyphony = app_state.settings.lock().unwrap().polyphony.clone();
+    match polyphony.max_voices {
+        0 => println!("复音数: 不限制"),
+        max_voices => println!("复音数: 上限 {}, 抢占策略 {:?}", max_voices, polyphony.stealing),
+    }
+    println!("{}", voice_stats_summary(&app_state.audio));
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -203,7 +731,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("MacOS Key Sound - 启动中...");
 
+    let record_path = match parse_args(std::env::args().skip(1))? {
+        RunMode::Replay(path) => {
+            let app_state = Arc::new(AppState::new(|settings| AudioEngine::new(settings.output_device.clone()))?);
+            return run_replay(app_state, &path);
+        }
+        RunMode::ListDevices => {
+            for name in audio_engine::output_device_names() {
+                println!("{}", name);
+            }
+            return Ok(());
+        }
+        RunMode::Diagnose => {
+            let app_state = AppState::new(|_| AudioEngine::offline())?;
+            run_diagnose(&app_state);
+            return Ok(());
+        }
+        RunMode::Render { session, output } => {
+            let app_state = AppState::new(|_| AudioEngine::offline())?;
+            return run_render(&app_state, &session, &output);
+        }
+        RunMode::Live { record } => record,
+    };
+
     // 首先初始化 Cocoa 应用（必须在主线程）
+    #[cfg(target_os = "macos")]
     unsafe {
         use cocoa::appkit::{NSApp, NSApplication, NSApplicationActivationPolicyAccessory};
         use cocoa::base::nil;
@@ -219,37 +771,95 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         info!("应用激活策略已设置为 Accessory");
     }
 
-    let app_state = Arc::new(AppState::new()?);
+    let app_state = Arc::new(AppState::new(|settings| AudioEngine::new(settings.output_device.clone()))?);
 
-    // 启动键盘监听线程
-    let app_state_for_keyboard = Arc::clone(&app_state);
-    thread::spawn(move || {
-        info!("键盘监听线程已启动 - 监听并播放音效");
+    let recorder = match record_path {
+        Some(path) => Some(Arc::new(SessionRecorder::create(&path)?)),
+        None => None,
+    };
 
-        let listen_result = listen(move |event| {
-            if let EventType::KeyPress(key) = &event.event_type {
-                info!("按下按键: {:?}", key);
-                app_state_for_keyboard.play_sound();
+    // 启动键盘监听，在后台线程中监听并播放音效
+    let app_state_for_keyboard = Arc::clone(&app_state);
+    let listener = Arc::new(listen(move |event| app_state_for_keyboard.handle_event(&event))?);
+    info!("键盘监听已启动 - 监听并播放音效");
+
+    // 录制是第二个订阅者，写入失败时取消订阅，不影响播放
+    if let Some(recorder) = &recorder {
+        let recorder_for_keyboard = Arc::clone(recorder);
+        let listener_for_recorder = Arc::downgrade(&listener);
+        let subscription = Arc::new(OnceLock::new());
+        let own_subscription = Arc::clone(&subscription);
+        let id = listener.subscribe(move |event| {
+            if let Err(e) = recorder_for_keyboard.record(&event) {
+                error!("写入录制文件失败，停止录制: {}", e);
+                if let (Some(listener), Some(id)) = (listener_for_recorder.upgrade(), own_subscription.get()) {
+                    listener.unsubscribe(*id);
+                }
             }
         });
+        let _ = subscription.set(id);
+    }
 
-        match listen_result {
+    // 等待监听结束并报告错误
+    let app_state_for_stats = Arc::clone(&app_state);
+    let listener_for_thread = Arc::clone(&listener);
+    let keyboard_thread = thread::spawn(move || {
+        match listener_for_thread.join() {
             Ok(_) => {
                 info!("键盘监听正常结束");
             }
             Err(error) => {
                 error!("键盘监听错误: {:?}", error);
-                error!("请检查辅助功能权限！");
-                error!("解决方案：系统偏好设置 → 安全性与隐私 → 隐私 → 辅助功能");
+                #[cfg(target_os = "macos")]
+                {
+                    error!("请检查辅助功能权限！");
+                    error!("解决方案：系统偏好设置 → 安全性与隐私 → 隐私 → 辅助功能");
+                }
+                #[cfg(target_os = "linux")]
+                error!("请检查 /dev/input 键盘设备的读取权限（input 用户组）");
             }
         }
 
+        if let Some(recorder) = &recorder {
+            info!("共录制 {} 个按键事件", recorder.count());
+        }
+        log_voice_stats(&app_state_for_stats.audio);
         info!("键盘监听线程结束");
     });
 
+    // 没有状态栏菜单的平台在前台运行，直到键盘监听结束。在终端中运行时输入 q 回车
+    // （或 Ctrl+D）停止监听，等监听线程输出统计后退出；Ctrl+C 直接结束进程
+    #[cfg(not(target_os = "macos"))]
+    {
+        use std::io::IsTerminal;
+        if std::io::stdin().is_terminal() {
+            info!("应用已启动，输入 q 回车退出");
+            let listener_for_stdin = Arc::clone(&listener);
+            thread::spawn(move || {
+                for line in std::io::stdin().lines() {
+                    match line {
+                        Ok(line) if line.trim() == "q" => break,
+                        Ok(_) => {}
+                        Err(_) => break,
+                    }
+                }
+                listener_for_stdin.stop();
+            });
+        } else {
+            info!("应用已启动，按 Ctrl+C 退出");
+        }
+        let _ = keyboard_thread.join();
+    }
+
+    // 键盘监听线程独立运行，主线程进入 Cocoa 事件循环；退出时停止监听并等待线程输出统计
+    #[cfg(target_os = "macos")]
+    let keyboard_thread = Arc::new(Mutex::new(Some(keyboard_thread)));
+
+    #[cfg(target_os = "macos")]
     info!("应用已启动，请查看系统托盘图标");
 
     // 使用原生 Cocoa API 创建菜单
+    #[cfg(target_os = "macos")]
     unsafe {
         use cocoa::appkit::NSApp;
         use cocoa::base::{nil, id};
@@ -265,6 +875,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         // 创建菜单构建函数 - 每次打开菜单时都会调用
         let app_state_for_menu = Arc::clone(&app_state);
+        let listener_for_menu = Arc::clone(&listener);
+        let keyboard_thread_for_menu = Arc::clone(&keyboard_thread);
         let menu_builder = Arc::new(Mutex::new(move |menu: id| {
             unsafe {
                 info!("菜单构建函数被调用");
@@ -286,6 +898,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 let toggle_item = native_menu::create_menu_item_with_callback_static(toggle_title, toggle_callback);
                 let _: () = cocoa::appkit::NSMenu::addItem_(menu, toggle_item);
 
+                // 鼠标点击和滚轮音效分别开关
+                let click_title = if app_state_ref.is_click_sound_enabled() {
+                    "● 鼠标点击音效"
+                } else {
+                    "○ 鼠标点击音效"
+                };
+                let app_state_click = Arc::clone(app_state_ref);
+                let click_callback = Arc::new(Mutex::new(move || {
+                    app_state_click.toggle_click_sound();
+                }));
+                let click_item = native_menu::create_menu_item_with_callback_static(click_title, click_callback);
+                let _: () = cocoa::appkit::NSMenu::addItem_(menu, click_item);
+
+                let scroll_title = if app_state_ref.is_scroll_sound_enabled() {
+                    "● 滚轮音效"
+                } else {
+                    "○ 滚轮音效"
+                };
+                let app_state_scroll = Arc::clone(app_state_ref);
+                let scroll_callback = Arc::new(Mutex::new(move || {
+                    app_state_scroll.toggle_scroll_sound();
+                }));
+                let scroll_item = native_menu::create_menu_item_with_callback_static(scroll_title, scroll_callback);
+                let _: () = cocoa::appkit::NSMenu::addItem_(menu, scroll_item);
+
                 // 添加分隔符
                 let separator = native_menu::create_separator_static();
                 let _: () = cocoa::appkit::NSMenu::addItem_(menu, separator);
@@ -328,9 +965,40 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 let separator3 = native_menu::create_separator_static();
                 let _: () = cocoa::appkit::NSMenu::addItem_(menu, separator3);
 
+                // 输出设备选项：系统默认 + 当前可用的设备；已保存但断开的设备也列出，便于切换
+                let current_device = app_state_ref.get_output_device();
+                let mut devices: Vec<Option<String>> = vec![None];
+                devices.extend(audio_engine::output_device_names().into_iter().map(Some));
+                if current_device.is_some() && !devices.contains(&current_device) {
+                    devices.push(current_device.clone());
+                }
+                for device in devices {
+                    let label = device.as_deref().unwrap_or("系统默认输出");
+                    let title = if device == current_device {
+                        format!("● {}", label)
+                    } else {
+                        format!("○ {}", label)
+                    };
+                    let app_state_device = Arc::clone(app_state_ref);
+                    let device_callback = Arc::new(Mutex::new(move || {
+                        app_state_device.set_output_device(device.clone());
+                    }));
+                    let device_item = native_menu::create_menu_item_with_callback_static(&title, device_callback);
+                    let _: () = cocoa::appkit::NSMenu::addItem_(menu, device_item);
+                }
+
+                let separator4 = native_menu::create_separator_static();
+                let _: () = cocoa::appkit::NSMenu::addItem_(menu, separator4);
+
                 // 添加退出菜单项
-                let quit_callback = Arc::new(Mutex::new(|| {
+                let listener_quit = Arc::clone(&listener_for_menu);
+                let keyboard_thread_quit = Arc::clone(&keyboard_thread_for_menu);
+                let quit_callback = Arc::new(Mutex::new(move || {
                     info!("用户请求退出应用");
+                    listener_quit.stop();
+                    if let Some(keyboard_thread) = keyboard_thread_quit.lock().unwrap().take() {
+                        let _ = keyboard_thread.join();
+                    }
                     unsafe {
                         use cocoa::appkit::NSApp;
                         let app = NSApp();
@@ -354,6 +1022,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[cfg(target_os = "macos")]
 fn create_tray_icon() -> Vec<u8> {
     // 尝试从文件加载图标，如果失败则使用程序化生成的后备图标
     if let Some(icon_data) = load_tray_icon_from_file() {
@@ -364,6 +1033,7 @@ fn create_tray_icon() -> Vec<u8> {
     create_fallback_tray_icon()
 }
 
+#[cfg(target_os = "macos")]
 fn load_tray_icon_from_file() -> Option<Vec<u8>> {
     // 构建多个可能的图标路径
     let mut icon_paths = Vec::new();
@@ -409,6 +1079,7 @@ fn load_tray_icon_from_file() -> Option<Vec<u8>> {
     None
 }
 
+#[cfg(target_os = "macos")]
 fn load_png_icon(path: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     // 使用image crate加载图片
     let img = image::open(path)?;
@@ -423,6 +1094,7 @@ fn load_png_icon(path: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     Ok(rgba_data)
 }
 
+#[cfg(target_os = "macos")]
 fn create_fallback_tray_icon() -> Vec<u8> {
     info!("使用改进的程序化状态栏图标");
     // 创建一个18x18像素的改进版音符图标
@@ -494,6 +1166,8 @@ fn load_settings() -> Settings {
     default_settings
 }
 
+// 目前只有 macOS 状态栏菜单会修改设置
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
 fn save_settings(settings: &Settings) {
     if let Some(config_dir) = dirs::config_dir() {
         let config_dir = config_dir.join("macos-key-sound");
@@ -506,7 +1180,33 @@ fn save_settings(settings: &Settings) {
     }
 }
 
-fn locate_sound_files() -> Vec<(String, PathBuf)> {
+// 逐个试解码音效包中的文件，去掉无法解码的样本，对应按键改用默认样本；全部无法解码时跳过该音效包
+fn probe_pack(mut pack: SoundPack) -> Result<SoundPack, String> {
+    let mut broken = Vec::new();
+    let mut last_error = None;
+    for path in pack.files() {
+        if let Err(e) = audio_engine::probe_file(&path) {
+            warn!("  音效包 {} 中的文件无法解码: {}", pack.name, e);
+            broken.push(path);
+            last_error = Some(e);
+        }
+    }
+    if broken.is_empty() {
+        return Ok(pack);
+    }
+    let affected = pack.remove_files(&broken);
+    if pack.is_empty() {
+        return Err(last_error.unwrap_or_default());
+    }
+    if !affected.is_empty() {
+        let names: Vec<_> = affected.iter().map(Key::name).collect();
+        let fallback = if pack.fallback.is_some() { "按下声改用默认样本" } else { "没有可用的默认样本" };
+        warn!("  音效包 {} 中以下按键的样本已移除，{}: {}", pack.name, fallback, names.join(", "));
+    }
+    Ok(pack)
+}
+
+fn locate_sound_files() -> Vec<(String, SoundSource)> {
     let mut sound_files = Vec::new();
     let mut asset_dirs = Vec::new();
 
@@ -550,16 +1250,45 @@ fn locate_sound_files() -> Vec<(String, PathBuf)> {
             info!("扫描音频目录: {}", assets_dir.display());
 
             if let Ok(entries) = std::fs::read_dir(assets_dir) {
-                for entry in entries.flatten() {
-                    let path = entry.path();
+                let mut entries: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+                entries.sort();
+                for path in entries {
+                    // 子目录可能是音效包，整个包作为一个可选条目
+                    if path.is_dir() {
+                        if sound_pack::is_mechvibes_pack(&path) {
+                            match sound_pack::load_mechvibes(&path).and_then(probe_pack) {
+                                Ok(pack) => {
+                                    info!("  找到音效包: {}", pack.name);
+                                    sound_files.push((pack.name.clone(), SoundSource::Pack(Arc::new(pack))));
+                                }
+                                Err(e) => warn!("  跳过音效包 {}: {}", path.display(), e),
+                            }
+                        } else if sound_pack::is_bucklespring_pack(&path) {
+                            match sound_pack::load_bucklespring(&path).and_then(probe_pack) {
+                                Ok(pack) => {
+                                    info!("  找到样本目录: {}", pack.name);
+                                    sound_files.push((pack.name.clone(), SoundSource::Pack(Arc::new(pack))));
+                                }
+                                Err(e) => warn!("  跳过样本目录 {}: {}", path.display(), e),
+                            }
+                        }
+                        continue;
+                    }
                     if path.is_file() {
                         if let Some(ext) = path.extension() {
                             let ext_str = ext.to_string_lossy().to_lowercase();
-                            if ext_str == "wav" || ext_str == "mp3" || ext_str == "m4a" || ext_str == "flac" {
+                            let supported = audio_engine::SUPPORTED_EXTENSIONS.contains(&ext_str.as_str());
+                            if supported || audio_engine::UNSUPPORTED_EXTENSIONS.contains(&ext_str.as_str()) {
                                 if let Some(filename) = path.file_name() {
                                     let display_name = filename.to_string_lossy().to_string();
-                                    info!("  找到音频文件: {}", display_name);
-                                    sound_files.push((display_name, path.clone()));
+                                    // 扫描时先探测能否解码，无法解码的文件不出现在音效列表中
+                                    match audio_engine::probe_file(&path) {
+                                        Ok(()) => {
+                                            info!("  找到音频文件: {}", display_name);
+                                            sound_files.push((display_name, SoundSource::File(path.clone())));
+                                        }
+                                        Err(e) => warn!("  跳过音频文件 {}: {}", display_name, e),
+                                    }
                                 }
                             }
                         }
@@ -575,10 +1304,209 @@ fn locate_sound_files() -> Vec<(String, PathBuf)> {
     }
 
     if sound_files.is_empty() {
-        error!("未找到任何音频文件");
+        warn!("未找到任何音频文件，只能使用内置合成音效");
     } else {
         info!("总共找到 {} 个音频文件", sound_files.len());
     }
 
     sound_files
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use audio_engine::CaptureSink;
+    use std::sync::mpsc;
+
+    // 只使用内置合成音效、输出到记录输出的应用状态
+    fn capture_state(settings: Settings) -> (AppState, CaptureSink) {
+        let capture = CaptureSink::new();
+        let sink = capture.clone();
+        let app_state = AppState::with_sounds(settings, Vec::new(), move |_| AudioEngine::with_sink(Box::new(sink)));
+        (app_state, capture)
+    }
+
+    fn synth_ref(app_state: &AppState, sound_name: &str) -> SampleRef {
+        app_state.get_sound_source(sound_name).unwrap().default_sample().unwrap()
+    }
+
+    #[test]
+    fn scripted_typing_reaches_the_sink() {
+        let settings = Settings {
+            volume: 0.5,
+            current_sound: "合成-click".to_string(),
+            key_sounds: HashMap::from([("KeyA".to_string(), "合成-thock".to_string())]),
+            normalize_loudness: false,
+            ..Settings::default()
+        };
+        let (app_state, capture) = capture_state(settings);
+        let app_state = Arc::new(app_state);
+
+        let source = ScriptedSource::typing(&[Key::KeyA, Key::KeyB], Duration::from_millis(1), Duration::from_millis(1));
+        let (done, finished) = mpsc::channel();
+        let done = Mutex::new(done);
+        let app_state_for_events = Arc::clone(&app_state);
+        let listener = listen_with(Box::new(source), move |event| {
+            app_state_for_events.handle_event(&event);
+            done.lock().unwrap().send(()).unwrap();
+        }).unwrap();
+        listener.join().unwrap();
+        assert_eq!(finished.try_iter().count(), 4);
+
+        // 合成音效没有松开样本，松开时不发声
+        let voices = capture.voices();
+        let played: Vec<_> = voices.iter().map(|voice| (voice.sample_ref.clone(), voice.params.gain)).collect();
+        assert_eq!(played, vec![
+            (synth_ref(&app_state, "合成-thock"), 0.5),
+            (synth_ref(&app_state, "合成-click"), 0.5),
+        ]);
+    }
+
+    // 带随机变调、音量抖动、轮换和声像的会话，渲染结果同时用于一致性测试和黄金值测试
+    fn render_golden_session() -> Vec<f32> {
+        let settings = Settings {
+            current_sound: "合成-clack".to_string(),
+            variations: HashMap::from([("合成-clack".to_string(), Variation {
+                pitch_cents: 50.0,
+                gain_jitter_db: 3.0,
+                pool: vec!["合成-thock".to_string()],
+            })]),
+            random_seed: 7,
+            pan_width: 0.5,
+            ..Settings::default()
+        };
+        let (app_state, _) = capture_state(settings);
+        let timeline: Vec<_> = [(0, Key::KeyA), (35, Key::KeyS), (36, Key::Space), (120, Key::Return)]
+            .into_iter()
+            .map(|(offset_ms, key)| (Duration::from_millis(offset_ms), Event::new(EventType::KeyPress(key))))
+            .collect();
+        render::render_session(&app_state.audio, &timeline, |event| app_state.voice_for_event(event))
+    }
+
+    fn wav_bytes(name: &str, samples: &[f32]) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!("macos-key-sound-{}-{}.wav", std::process::id(), name));
+        render::write_wav(&path, samples).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        bytes
+    }
+
+    // 样本量化为 16 位后的 FNV-1a 哈希。量化可以吸收不同平台数学库在最低位上的差异
+    fn golden_hash(samples: &[f32]) -> u64 {
+        samples.iter()
+            .flat_map(|sample| ((sample.clamp(-1.0, 1.0) * 32767.0).round() as i16).to_le_bytes())
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+    }
+
+    // 黄金值：有意改变合成器、混音、变化或声像时，运行
+    //   cargo test render_matches_golden -- --nocapture
+    // 按失败信息中的实际值更新这两个常量，并在提交说明中写明输出为什么改变
+    const GOLDEN_FRAMES: usize = 14571;
+    const GOLDEN_HASH: u64 = 0xfd93_1a0d_70e5_19fe;
+
+    #[test]
+    fn render_matches_golden() {
+        let samples = render_golden_session();
+        let frames = samples.len() / audio_engine::OUTPUT_CHANNELS as usize;
+        let hash = golden_hash(&samples);
+        println!("渲染结果: GOLDEN_FRAMES = {}, GOLDEN_HASH = {:#018x}", frames, hash);
+        assert_eq!((frames, hash), (GOLDEN_FRAMES, GOLDEN_HASH), "渲染结果与黄金值不同");
+    }
+
+    #[test]
+    fn render_is_byte_identical_across_runs() {
+        let first = wav_bytes("first", &render_golden_session());
+        let second = wav_bytes("second", &render_golden_session());
+        // 44 字节 WAV 头之后还有样本
+        assert!(first.len() > 44);
+        assert!(first == second, "两次渲染的 WAV 文件不同");
+    }
+
+    #[test]
+    fn loudness_is_analysed_before_playback() {
+        let settings = Settings {
+            current_sound: "合成-clack".to_string(),
+            key_sounds: HashMap::from([("KeyA".to_string(), "合成-click".to_string())]),
+            variations: HashMap::from([("合成-clack".to_string(), Variation {
+                pool: vec!["合成-thock".to_string()],
+                ..Variation::default()
+            })]),
+            ..Settings::default()
+        };
+        let (app_state, capture) = capture_state(settings);
+        let analysed = |app_state: &AppState| {
+            let mut names: Vec<_> = app_state.loudness.lock().unwrap().keys().cloned().collect();
+            names.sort();
+            names
+        };
+        let preloaded = analysed(&app_state);
+        assert_eq!(preloaded, vec!["合成-clack", "合成-click", "合成-thock"]);
+
+        for key in [Key::KeyA, Key::KeyS, Key::KeyD, Key::KeyF] {
+            app_state.handle_event(&Event::new(EventType::KeyPress(key)));
+        }
+        assert_eq!(capture.voices().len(), 4);
+        assert_eq!(analysed(&app_state), preloaded);
+        // 归一化后的增益与未归一化的不同，说明读到了缓存的响度
+        assert!(capture.voices().iter().all(|voice| voice.params.gain != 0.7));
+    }
+
+    #[test]
+    fn loudness_is_read_from_cache_after_preload() {
+        let settings = Settings { current_sound: "合成-clack".to_string(), ..Settings::default() };
+        let (app_state, capture) = capture_state(settings);
+        let analysed = app_state.sound_loudness("合成-clack").unwrap();
+        assert!((app_state.normalization_gain("合成-clack") - loudness::normalization_gain(analysed)).abs() < 1e-6);
+
+        // 改写缓存后查询和播放都使用缓存的值，不再重新分析
+        app_state.loudness.lock().unwrap().insert("合成-clack".to_string(), Some(loudness::TARGET_DB - 6.0));
+        assert_eq!(app_state.sound_loudness("合成-clack"), Some(loudness::TARGET_DB - 6.0));
+        let gain = loudness::normalization_gain(loudness::TARGET_DB - 6.0);
+        app_state.handle_event(&Event::new(EventType::KeyPress(Key::KeyA)));
+        let unnormalized = app_state.settings.lock().unwrap().volume;
+        let played = capture.voices()[0].params.gain;
+        assert!((played / unnormalized - gain).abs() < 1e-3, "播放音量 {}，期望 {} × {}", played, unnormalized, gain);
+
+        // 标记为静音的音效不做归一化
+        app_state.loudness.lock().unwrap().insert("合成-clack".to_string(), None);
+        assert_eq!(app_state.normalization_gain("合成-clack"), 1.0);
+    }
+
+    #[test]
+    fn voice_stats_summary_counts_stolen_voices() {
+        let settings = Settings {
+            current_sound: "合成-click".to_string(),
+            polyphony: PolyphonySettings { max_voices: 4, ..PolyphonySettings::default() },
+            ..Settings::default()
+        };
+        let (app_state, _) = capture_state(settings);
+        assert_eq!(voice_stats_summary(&app_state.audio), "声部统计: 抢占 0 个, 丢弃 0 个");
+
+        // 同一时刻的 10 个不同按键超过上限 4，最早的 6 个声部被抢占
+        let keys = [Key::KeyA, Key::KeyB, Key::KeyC, Key::KeyD, Key::KeyE, Key::KeyF, Key::KeyG, Key::KeyH, Key::KeyI, Key::KeyJ];
+        let timeline: Vec<_> = keys.into_iter().map(|key| (Duration::ZERO, Event::new(EventType::KeyPress(key)))).collect();
+        render::render_session(&app_state.audio, &timeline, |event| app_state.voice_for_event(event));
+        assert_eq!(voice_stats_summary(&app_state.audio), "声部统计: 抢占 6 个, 丢弃 0 个");
+    }
+
+    #[test]
+    fn parse_args_ignores_process_serial_number() {
+        let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().into_iter();
+        assert_eq!(parse_args(args(&["-psn_0_12345"])), Ok(RunMode::Live { record: None }));
+        assert_eq!(parse_args(args(&["--verbose", "--record", "a.jsonl"])), Ok(RunMode::Live { record: Some(PathBuf::from("a.jsonl")) }));
+        assert_eq!(parse_args(args(&["-psn_0_1", "--replay", "a.jsonl"])), Ok(RunMode::Replay(PathBuf::from("a.jsonl"))));
+        assert!(parse_args(args(&["--record"])).is_err());
+    }
+
+    #[test]
+    fn disabled_keyboard_source_plays_nothing() {
+        let settings = Settings {
+            current_sound: "合成-click".to_string(),
+            keyboard_sounds_enabled: false,
+            ..Settings::default()
+        };
+        let (app_state, capture) = capture_state(settings);
+        app_state.handle_event(&Event::new(EventType::KeyPress(Key::KeyA)));
+        assert!(capture.voices().is_empty());
+    }
+}
@@ -0,0 +1,102 @@
+// 离线渲染 - 把按键会话按采样精度混音并写入 WAV 文件，不需要声卡
+use log::info;
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::audio_engine::{AudioEngine, SampleRef, VoiceParams, OUTPUT_CHANNELS, OUTPUT_SAMPLE_RATE};
+use crate::keyboard_adapter::Event;
+
+// 事件偏移换算为输出帧序号，使用整数运算保证同一会话每次渲染结果完全一致
+fn offset_to_frame(offset: Duration) -> usize {
+    (offset.as_nanos() * OUTPUT_SAMPLE_RATE as u128 / 1_000_000_000) as usize
+}
+
+// 渲染事件时间线；voice_for 决定每个事件播放的样本和音量，应与实时播放使用同一套规则
+pub fn render_session<F>(engine: &AudioEngine, timeline: &[(Duration, Event)], voice_for: F) -> Vec<f32>
+where
+    F: Fn(&Event) -> Option<(SampleRef, VoiceParams)>,
+{
+    // 事件时间按会话偏移换算，限制重复频率等依赖时间的规则与实时播放一致
+    let start = Instant::now();
+    let schedule = timeline
+        .iter()
+        .filter_map(|(offset, event)| {
+            let mut event = event.clone();
+            event.time = start + *offset;
+            let (sample_ref, params) = voice_for(&event)?;
+            let sample = engine.load(&sample_ref)?;
+            Some((offset_to_frame(*offset), sample, params))
+        })
+        .collect::<Vec<_>>();
+    info!("离线渲染: {} 个事件, {} 个声部", timeline.len(), schedule.len());
+    engine.render_offline(schedule)
+}
+
+// 以 32 位浮点 WAV 保存混音结果，样本值与实时输出完全相同
+pub fn write_wav(path: &Path, samples: &[f32]) -> Result<(), String> {
+    let spec = hound::WavSpec {
+        channels: OUTPUT_CHANNELS,
+        sample_rate: OUTPUT_SAMPLE_RATE,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(path, spec)
+        .map_err(|e| format!("无法创建 WAV 文件 {}: {:?}", path.display(), e))?;
+    for sample in samples {
+        writer.write_sample(*sample)
+            .map_err(|e| format!("写入 WAV 文件 {} 失败: {:?}", path.display(), e))?;
+    }
+    writer.finalize()
+        .map_err(|e| format!("写入 WAV 文件 {} 失败: {:?}", path.display(), e))?;
+
+    let frames = samples.len() / OUTPUT_CHANNELS as usize;
+    info!("已写入 WAV 文件: {} ({:.3} 秒)", path.display(), frames as f64 / OUTPUT_SAMPLE_RATE as f64);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard_adapter::{EventType, Key};
+    use crate::synth::SynthPreset;
+
+    fn press_at(offset_ms: u64) -> (Duration, Event) {
+        (Duration::from_millis(offset_ms), Event::new(EventType::KeyPress(Key::KeyA)))
+    }
+
+    fn render(timeline: &[(Duration, Event)]) -> Vec<f32> {
+        let engine = AudioEngine::offline();
+        let click = SampleRef::Synth(crate::synth::synth_sounds(&Default::default())
+            .into_iter()
+            .find(|(name, _)| name == SynthPreset::Click.sound_name())
+            .unwrap().1);
+        render_session(&engine, timeline, |_| Some((click.clone(), VoiceParams::new(0.5))))
+    }
+
+    #[test]
+    fn offsets_convert_to_exact_frames() {
+        assert_eq!(offset_to_frame(Duration::ZERO), 0);
+        assert_eq!(offset_to_frame(Duration::from_millis(10)), 441);
+        assert_eq!(offset_to_frame(Duration::from_secs(2)), 88_200);
+    }
+
+    #[test]
+    fn voice_starts_on_its_frame() {
+        let at_start = render(&[press_at(0)]);
+        let delayed = render(&[press_at(10)]);
+        let silence = 441 * OUTPUT_CHANNELS as usize;
+        assert!(delayed[..silence].iter().all(|value| *value == 0.0));
+        assert_eq!(delayed[silence..], at_start[..]);
+    }
+
+    #[test]
+    fn overlapping_voices_are_summed() {
+        let single = render(&[press_at(0)]);
+        let double = render(&[press_at(0), press_at(0)]);
+        assert_eq!(double.len(), single.len());
+        for (one, two) in single.iter().zip(&double) {
+            assert!((one * 2.0 - two).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+// 按键自动重复 - 按住 Backspace、方向键时系统会连续产生按下事件，按设置决定其中哪些发声
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::keyboard_adapter::Key;
+
+// 自动重复的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepeatMode {
+    // 每次重复都播放
+    #[default]
+    Every,
+    // 只播放第一次按下
+    FirstOnly,
+    // 重复时播放更轻的声音（repeat_sound，未配置时使用原音效）
+    Softer,
+    // 每个按键每秒最多播放 max_per_second 次
+    Throttle,
+}
+
+// 自动重复设置
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RepeatSettings {
+    pub mode: RepeatMode,
+    pub repeat_sound: Option<String>, // softer 模式下重复时使用的声音文件名
+    pub repeat_gain: f32, // softer 模式下重复声音的音量倍数
+    pub max_per_second: f32, // throttle 模式下每个按键每秒最多播放的次数
+}
+
+impl Default for RepeatSettings {
+    fn default() -> Self {
+        RepeatSettings {
+            mode: RepeatMode::Every,
+            repeat_sound: None,
+            repeat_gain: 0.5,
+            max_per_second: 10.0,
+        }
+    }
+}
+
+// 一次按下事件的处理结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepeatDecision {
+    Play,
+    // 播放更轻的重复声音
+    Soft,
+    Skip,
+}
+
+// 记录每个按键上一次发声的时间，用于限制重复频率
+#[derive(Default)]
+pub struct RepeatFilter {
+    last_played: Mutex<HashMap<Key, Instant>>,
+}
+
+impl RepeatFilter {
+    // 决定按下事件是否发声；time 为事件发生的时间，离线渲染时由会话偏移换算
+    pub fn decide(&self, settings: &RepeatSettings, key: &Key, is_repeat: bool, time: Instant) -> RepeatDecision {
+        let decision = match (is_repeat, settings.mode) {
+            (false, _) | (true, RepeatMode::Every) => RepeatDecision::Play,
+            (true, RepeatMode::FirstOnly) => RepeatDecision::Skip,
+            (true, RepeatMode::Softer) => RepeatDecision::Soft,
+            (true, RepeatMode::Throttle) => {
+                let interval = Duration::from_secs_f32(1.0 / settings.max_per_second.max(0.1));
+                match self.last_played.lock().unwrap().get(key) {
+                    Some(last) if time.saturating_duration_since(*last) < interval => RepeatDecision::Skip,
+                    _ => RepeatDecision::Play,
+                }
+            }
+        };
+        if decision != RepeatDecision::Skip {
+            self.last_played.lock().unwrap().insert(key.clone(), time);
+        }
+        decision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use RepeatDecision::{Play, Skip, Soft};
+
+    // 同一段按键过程：按住 A 产生系统自动重复，松开后再次按下并按住，最后是另一个键的重复事件
+    // (事件时间 ms, 按键, 是否为自动重复)
+    const STEPS: &[(u64, Key, bool)] = &[
+        (0, Key::KeyA, false),
+        (30, Key::KeyA, true),
+        (60, Key::KeyA, true),
+        (130, Key::KeyA, true),
+        (140, Key::KeyA, false),
+        (150, Key::KeyA, true),
+        (160, Key::KeyB, true),
+    ];
+
+    #[test]
+    fn decides_each_step_by_mode() {
+        // throttle 模式每秒 10 次，即同一按键两次发声至少间隔 100 ms
+        let cases = [
+            (RepeatMode::Every, [Play, Play, Play, Play, Play, Play, Play]),
+            (RepeatMode::FirstOnly, [Play, Skip, Skip, Skip, Play, Skip, Skip]),
+            (RepeatMode::Softer, [Play, Soft, Soft, Soft, Play, Soft, Soft]),
+            (RepeatMode::Throttle, [Play, Skip, Skip, Play, Play, Skip, Play]),
+        ];
+        for (mode, expected) in cases {
+            let settings = RepeatSettings { mode, max_per_second: 10.0, ..RepeatSettings::default() };
+            let filter = RepeatFilter::default();
+            let start = Instant::now();
+            let decisions: Vec<_> = STEPS.iter()
+                .map(|(offset_ms, key, is_repeat)| filter.decide(&settings, key, *is_repeat, start + Duration::from_millis(*offset_ms)))
+                .collect();
+            assert_eq!(decisions, expected, "{:?} 模式", mode);
+        }
+    }
+
+    #[test]
+    fn first_press_always_plays() {
+        let start = Instant::now();
+        for mode in [RepeatMode::Every, RepeatMode::FirstOnly, RepeatMode::Softer, RepeatMode::Throttle] {
+            let settings = RepeatSettings { mode, ..RepeatSettings::default() };
+            let filter = RepeatFilter::default();
+            // 快速连续敲击（非自动重复）不受任何模式限制
+            for offset_ms in [0, 5, 10] {
+                assert_eq!(filter.decide(&settings, &Key::KeyA, false, start + Duration::from_millis(offset_ms)), Play);
+            }
+        }
+    }
+
+    #[test]
+    fn throttle_guards_against_tiny_rates() {
+        // max_per_second 为 0 时按每秒 0.1 次计算，不会除以零
+        let settings = RepeatSettings { mode: RepeatMode::Throttle, max_per_second: 0.0, ..RepeatSettings::default() };
+        let filter = RepeatFilter::default();
+        let start = Instant::now();
+        assert_eq!(filter.decide(&settings, &Key::KeyA, false, start), Play);
+        assert_eq!(filter.decide(&settings, &Key::KeyA, true, start + Duration::from_secs(9)), Skip);
+        assert_eq!(filter.decide(&settings, &Key::KeyA, true, start + Duration::from_secs(10)), Play);
+    }
+}
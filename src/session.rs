@@ -0,0 +1,207 @@
+// 按键会话 - 把带时间戳的按键事件录制为 JSON lines 文件，并读取回来用于回放
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::keyboard_adapter::{Event, EventType, Modifiers};
+
+// 文件中的一行，如 {"time_us":1250,"type":"KeyPress","key":"KeyA","repeat":false,"modifiers":1,"device":3}
+#[derive(Serialize, Deserialize)]
+struct SessionLine {
+    time_us: u64, // 距第一个事件的微秒数
+    #[serde(flatten)]
+    event_type: EventType,
+    #[serde(default)]
+    repeat: bool,
+    #[serde(default)]
+    modifiers: Modifiers,
+    #[serde(default)]
+    device: u32,
+}
+
+struct RecorderState {
+    writer: BufWriter<File>,
+    start: Option<Instant>,
+    count: usize,
+}
+
+// 会话录制器：时间从收到第一个事件开始计算，每条事件写入后立即刷新，异常退出也不会丢失
+pub struct SessionRecorder {
+    state: Mutex<RecorderState>,
+}
+
+impl SessionRecorder {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("无法创建录制文件 {}: {:?}", path.display(), e))?;
+        info!("开始录制按键会话: {}", path.display());
+        Ok(SessionRecorder {
+            state: Mutex::new(RecorderState {
+                writer: BufWriter::new(file),
+                start: None,
+                count: 0,
+            }),
+        })
+    }
+
+    // 写入一个事件，失败时返回错误，调用方决定是否继续录制
+    pub fn record(&self, event: &Event) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let start = *state.start.get_or_insert(event.time);
+        let line = SessionLine {
+            time_us: event.time.saturating_duration_since(start).as_micros() as u64,
+            event_type: event.event_type.clone(),
+            repeat: event.is_repeat,
+            modifiers: event.modifiers,
+            device: event.device,
+        };
+
+        let json = serde_json::to_string(&line).map_err(|e| e.to_string())?;
+        writeln!(state.writer, "{}", json)
+            .and_then(|_| state.writer.flush())
+            .map_err(|e| e.to_string())?;
+        state.count += 1;
+        Ok(())
+    }
+
+    pub fn count(&self) -> usize {
+        self.state.lock().unwrap().count
+    }
+}
+
+// 读取会话文件，返回每个事件距开始的偏移；空行会被跳过，格式错误时报告行号
+pub fn load_session(path: &Path) -> Result<Vec<(Duration, Event)>, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("无法打开会话文件 {}: {:?}", path.display(), e))?;
+
+    let mut events = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("读取会话文件 {} 失败: {:?}", path.display(), e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let parsed: SessionLine = serde_json::from_str(&line)
+            .map_err(|e| format!("会话文件 {} 第 {} 行格式错误: {}", path.display(), index + 1, e))?;
+
+        let mut event = Event::new(parsed.event_type);
+        event.is_repeat = parsed.repeat;
+        event.modifiers = parsed.modifiers;
+        event.device = parsed.device;
+        events.push((Duration::from_micros(parsed.time_us), event));
+    }
+
+    info!("已读取会话文件: {} ({} 个事件)", path.display(), events.len());
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard_adapter::{Button, Key};
+
+    use std::path::PathBuf;
+
+    fn temp_session(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("macos-key-sound-{}-{}.jsonl", std::process::id(), name))
+    }
+
+    fn event(event_type: EventType, at: Instant, modifiers: Modifiers, device: u32) -> Event {
+        let mut event = Event::new(event_type);
+        event.time = at;
+        event.modifiers = modifiers;
+        event.device = device;
+        event
+    }
+
+    #[test]
+    fn recorded_session_loads_back() {
+        let path = temp_session("round-trip");
+        let start = Instant::now();
+        let mut shift_caps = Modifiers::SHIFT;
+        shift_caps.insert(Modifiers::CAPS_LOCK);
+        let mut repeated = event(EventType::KeyPress(Key::KeyA), start + Duration::from_micros(500_000), Modifiers::SHIFT, 3);
+        repeated.is_repeat = true;
+        let recorded = vec![
+            event(EventType::KeyPress(Key::KeyA), start, shift_caps, 3),
+            repeated,
+            event(EventType::KeyRelease(Key::KeyA), start + Duration::from_micros(612_345), Modifiers::empty(), 3),
+            event(EventType::ButtonPress(Button::Right), start + Duration::from_millis(900), Modifiers::CONTROL, 0),
+            event(EventType::Wheel { delta_x: -1, delta_y: 2 }, start + Duration::from_secs(1), Modifiers::empty(), 0),
+            event(EventType::KeyPress(Key::Unknown(0x1234)), start + Duration::from_secs(2), Modifiers::META, 7),
+        ];
+
+        let recorder = SessionRecorder::create(&path).unwrap();
+        for event in &recorded {
+            recorder.record(event).unwrap();
+        }
+        assert_eq!(recorder.count(), recorded.len());
+        drop(recorder);
+
+        let loaded = load_session(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.len(), recorded.len());
+        for ((offset, loaded), recorded) in loaded.iter().zip(&recorded) {
+            assert_eq!(*offset, recorded.time - start);
+            assert_eq!(loaded.event_type, recorded.event_type);
+            assert_eq!(loaded.is_repeat, recorded.is_repeat);
+            assert_eq!(loaded.modifiers, recorded.modifiers);
+            assert_eq!(loaded.device, recorded.device);
+        }
+    }
+
+    #[test]
+    fn offsets_are_relative_to_first_event() {
+        let path = temp_session("offsets");
+        // 第一个事件之前的时间不计入，偏移从 0 开始
+        let start = Instant::now() + Duration::from_secs(5);
+        let recorder = SessionRecorder::create(&path).unwrap();
+        for offset_ms in [0, 86, 87, 1500] {
+            let at = start + Duration::from_millis(offset_ms);
+            recorder.record(&event(EventType::KeyPress(Key::KeyB), at, Modifiers::empty(), 0)).unwrap();
+        }
+        drop(recorder);
+
+        let offsets: Vec<_> = load_session(&path).unwrap().into_iter().map(|(offset, _)| offset).collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(offsets, [0, 86, 87, 1500].map(Duration::from_millis));
+    }
+
+    #[test]
+    fn reads_documented_format() {
+        let path = temp_session("format");
+        std::fs::write(&path, concat!(
+            r#"{"time_us":0,"type":"KeyPress","key":"KeyA","repeat":false,"modifiers":1,"device":3}"#, "\n",
+            "\n",
+            // repeat、modifiers、device 可以省略
+            r#"{"time_us":86000,"type":"KeyRelease","key":"KeyA"}"#, "\n",
+            r#"{"time_us":90000,"type":"Wheel","key":{"delta_x":0,"delta_y":-3}}"#, "\n",
+        )).unwrap();
+        let loaded = load_session(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(loaded[0].1.modifiers, Modifiers::SHIFT);
+        assert_eq!(loaded[0].1.device, 3);
+        assert_eq!(loaded[1].0, Duration::from_millis(86));
+        assert_eq!(loaded[1].1.event_type, EventType::KeyRelease(Key::KeyA));
+        assert_eq!(loaded[2].1.event_type, EventType::Wheel { delta_x: 0, delta_y: -3 });
+    }
+
+    #[test]
+    fn malformed_line_reports_line_number() {
+        let path = temp_session("malformed");
+        std::fs::write(&path, concat!(
+            r#"{"time_us":0,"type":"KeyPress","key":"KeyA"}"#, "\n",
+            "\n",
+            r#"{"time_us":10,"type":"KeyPress""#, "\n",
+        )).unwrap();
+        let error = load_session(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(error.contains("第 3 行"), "{}", error);
+    }
+}
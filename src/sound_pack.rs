@@ -0,0 +1,518 @@
+// 音效包 - 按键到样本的映射（Mechvibes、bucklespring 等多样本格式）
+use log::{debug, info, warn};
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::audio_engine::SampleRef;
+use crate::synth::SynthVoicing;
+use crate::keyboard_adapter::{evdev_code_to_key, scancode_to_key, Key};
+
+// 菜单中可选择的一个音效条目
+pub enum SoundSource {
+    File(PathBuf),
+    Pack(Arc<SoundPack>),
+    Synth(SynthVoicing),
+}
+
+impl SoundSource {
+    // 按下按键时要播放的样本
+    pub fn press_sample(&self, key: &Key) -> Option<SampleRef> {
+        match self {
+            SoundSource::File(path) => Some(SampleRef::file(path.clone())),
+            SoundSource::Pack(pack) => pack.press_sample(key).cloned(),
+            SoundSource::Synth(voicing) => Some(SampleRef::Synth(*voicing)),
+        }
+    }
+
+    // 松开按键时要播放的样本，单文件音效没有松开样本
+    pub fn release_sample(&self, key: &Key) -> Option<SampleRef> {
+        match self {
+            SoundSource::File(_) | SoundSource::Synth(_) => None,
+            SoundSource::Pack(pack) => pack.release_sample(key).cloned(),
+        }
+    }
+
+    // 不对应任何按键的样本（鼠标点击、滚轮），音效包使用默认样本
+    pub fn default_sample(&self) -> Option<SampleRef> {
+        match self {
+            SoundSource::File(path) => Some(SampleRef::file(path.clone())),
+            SoundSource::Pack(pack) => pack.fallback.clone(),
+            SoundSource::Synth(voicing) => Some(SampleRef::Synth(*voicing)),
+        }
+    }
+
+    // 需要预先解码的所有样本
+    pub fn all_samples(&self) -> Vec<SampleRef> {
+        match self {
+            SoundSource::File(path) => vec![SampleRef::file(path.clone())],
+            SoundSource::Pack(pack) => pack.press.values()
+                .chain(pack.release.values())
+                .chain(pack.fallback.iter())
+                .cloned()
+                .collect(),
+            SoundSource::Synth(voicing) => vec![SampleRef::Synth(*voicing)],
+        }
+    }
+
+    // 日志中显示的来源
+    pub fn location(&self) -> String {
+        match self {
+            SoundSource::File(path) => path.display().to_string(),
+            SoundSource::Pack(pack) => pack.dir.display().to_string(),
+            SoundSource::Synth(voicing) => SampleRef::Synth(*voicing).to_string(),
+        }
+    }
+}
+
+// 多样本音效包：每个按键可以有自己的按下/松开样本
+pub struct SoundPack {
+    pub name: String,
+    pub dir: PathBuf,
+    pub press: HashMap<Key, SampleRef>,
+    pub release: HashMap<Key, SampleRef>,
+    pub fallback: Option<SampleRef>, // 包中没有定义的按键使用的样本
+}
+
+impl SoundPack {
+    // 包中引用的全部音频文件（去重）
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = self.press.values()
+            .chain(self.release.values())
+            .chain(self.fallback.iter())
+            .filter_map(|sample_ref| match sample_ref {
+                SampleRef::File { path, .. } => Some(path.clone()),
+                SampleRef::Synth(_) => None,
+            })
+            .collect();
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    // 移除引用了指定文件的样本，这些按键改用 fallback（松开样本则不再发声）；
+    // 返回受影响的按键，fallback 本身被移除时返回值不包括未定义的按键
+    pub fn remove_files(&mut self, files: &[PathBuf]) -> Vec<Key> {
+        let uses_file = |sample_ref: &SampleRef| matches!(sample_ref, SampleRef::File { path, .. } if files.contains(path));
+        let mut affected: Vec<Key> = self.press.iter()
+            .chain(self.release.iter())
+            .filter(|(_, sample_ref)| uses_file(sample_ref))
+            .map(|(key, _)| key.clone())
+            .collect();
+        affected.sort_by_key(Key::name);
+        affected.dedup();
+        self.press.retain(|_, sample_ref| !uses_file(sample_ref));
+        self.release.retain(|_, sample_ref| !uses_file(sample_ref));
+        if self.fallback.as_ref().is_some_and(uses_file) {
+            self.fallback = None;
+        }
+        affected
+    }
+
+    // 没有任何可以播放的样本
+    pub fn is_empty(&self) -> bool {
+        self.press.is_empty() && self.release.is_empty() && self.fallback.is_none()
+    }
+
+    pub fn press_sample(&self, key: &Key) -> Option<&SampleRef> {
+        self.press.get(key).or(self.fallback.as_ref())
+    }
+
+    pub fn release_sample(&self, key: &Key) -> Option<&SampleRef> {
+        self.release.get(key)
+    }
+}
+
+// 判断目录是否为 Mechvibes 音效包（包含 config.json）
+pub fn is_mechvibes_pack(dir: &Path) -> bool {
+    dir.join("config.json").is_file()
+}
+
+// 音效包中引用的文件名只能指向包目录内部：拒绝绝对路径、盘符前缀和 ".."，
+// 避免下载的音效包读取包目录以外的文件
+fn pack_file(dir: &Path, file: &str) -> Result<PathBuf, String> {
+    let relative = Path::new(file);
+    let escapes = relative.components().any(|component| {
+        matches!(component, Component::RootDir | Component::Prefix(_) | Component::ParentDir)
+    });
+    if escapes || file.is_empty() {
+        return Err(format!("文件名不能指向音效包目录以外: {}", file));
+    }
+    Ok(dir.join(relative))
+}
+
+// 读取 Mechvibes 音效包目录
+//
+// config.json 的 key_define_type 为 "single" 时，defines 中每个扫描码对应音频精灵
+// sound 里的 [起始毫秒, 时长毫秒]；为 "multi" 时，每个扫描码对应一个独立文件名。
+pub fn load_mechvibes(dir: &Path) -> Result<SoundPack, String> {
+    let config_path = dir.join("config.json");
+    let content = std::fs::read_to_string(&config_path)
+        .map_err(|e| format!("无法读取 {}: {:?}", config_path.display(), e))?;
+    let config: Value = serde_json::from_str(&content)
+        .map_err(|e| format!("解析 {} 失败: {}", config_path.display(), e))?;
+
+    let name = config.get("name")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .unwrap_or_else(|| dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default());
+    let define_type = config.get("key_define_type").and_then(Value::as_str).unwrap_or("single");
+    let defines = config.get("defines")
+        .and_then(Value::as_object)
+        .ok_or_else(|| format!("{} 缺少 defines", config_path.display()))?;
+
+    let sprite = match define_type {
+        "single" => {
+            let sound = config.get("sound")
+                .and_then(Value::as_str)
+                .ok_or_else(|| format!("{} 缺少 sound", config_path.display()))?;
+            let sprite = pack_file(dir, sound)?;
+            if !sprite.is_file() {
+                return Err(format!("音频精灵文件不存在: {}", sprite.display()));
+            }
+            Some(sprite)
+        }
+        "multi" => None,
+        other => return Err(format!("不支持的 key_define_type: {}", other)),
+    };
+
+    let mut press = HashMap::new();
+    let mut by_scancode = Vec::new();
+    for (code, define) in defines {
+        let scancode = match code.parse::<u32>() {
+            Ok(scancode) => scancode,
+            Err(_) => {
+                debug!("忽略无法识别的扫描码: {}", code);
+                continue;
+            }
+        };
+
+        let sample_ref = match (&sprite, define) {
+            (Some(sprite), Value::Array(range)) if range.len() == 2 => {
+                match (range[0].as_u64(), range[1].as_u64()) {
+                    (Some(start), Some(duration)) => SampleRef::segment(sprite.clone(), start as u32, duration as u32),
+                    _ => continue,
+                }
+            }
+            (None, Value::String(file)) => {
+                let path = match pack_file(dir, file) {
+                    Ok(path) => path,
+                    Err(e) => {
+                        warn!("音效包 {} 忽略扫描码 {}: {}", name, code, e);
+                        continue;
+                    }
+                };
+                if !path.is_file() {
+                    warn!("音效包 {} 缺少文件: {}", name, path.display());
+                    continue;
+                }
+                SampleRef::file(path)
+            }
+            // null 表示该键没有声音
+            _ => continue,
+        };
+
+        by_scancode.push((scancode, sample_ref.clone()));
+        press.insert(scancode_to_key(scancode), sample_ref);
+    }
+
+    if press.is_empty() {
+        return Err(format!("音效包 {} 没有可用的按键定义", name));
+    }
+
+    // 未定义的按键使用 A 键（扫描码 0x1E）的声音，没有则用扫描码最小的那个
+    by_scancode.sort_by_key(|(scancode, _)| *scancode);
+    let fallback = press.get(&Key::KeyA)
+        .cloned()
+        .or_else(|| by_scancode.first().map(|(_, sample_ref)| sample_ref.clone()));
+
+    info!("已加载 Mechvibes 音效包: {} ({} 模式, {} 个按键)", name, define_type, press.len());
+    Ok(SoundPack {
+        name,
+        dir: dir.to_path_buf(),
+        press,
+        release: HashMap::new(),
+        fallback,
+    })
+}
+
+// 解析 bucklespring 样本文件名 "XX-N.wav"：XX 为十六进制键码，N 为 1（按下）或 0（松开）
+fn parse_bucklespring_name(file_name: &str) -> Option<(u16, bool)> {
+    let stem = file_name.strip_suffix(".wav")?;
+    let (code, state) = stem.split_once('-')?;
+    if code.len() != 2 {
+        return None;
+    }
+    let code = u16::from_str_radix(code, 16).ok()?;
+    match state {
+        "1" => Some((code, true)),
+        "0" => Some((code, false)),
+        _ => None,
+    }
+}
+
+// 已知按键中没有出现在 codes 里的键码，按键码排序
+fn missing_codes(codes: &[u16]) -> Vec<u16> {
+    (1..=255)
+        .filter(|code| !matches!(evdev_code_to_key(*code), Key::Unknown(_)))
+        .filter(|code| !codes.contains(code))
+        .collect()
+}
+
+// 判断目录是否为 bucklespring 样本目录（包含 XX-0.wav / XX-1.wav 文件）
+pub fn is_bucklespring_pack(dir: &Path) -> bool {
+    match std::fs::read_dir(dir) {
+        Ok(entries) => entries.flatten().any(|entry| {
+            parse_bucklespring_name(&entry.file_name().to_string_lossy()).is_some()
+        }),
+        Err(_) => false,
+    }
+}
+
+// 读取 bucklespring 样本目录，建立每个按键按下/松开两个方向的样本表
+pub fn load_bucklespring(dir: &Path) -> Result<SoundPack, String> {
+    let name = dir.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| dir.display().to_string());
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| format!("无法读取 {}: {:?}", dir.display(), e))?;
+
+    let mut press = HashMap::new();
+    let mut release = HashMap::new();
+    let mut press_codes = Vec::new();
+    let mut release_codes = Vec::new();
+    let mut unmapped = Vec::new();
+    for entry in entries.flatten() {
+        let (code, pressed) = match parse_bucklespring_name(&entry.file_name().to_string_lossy()) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+        let key = evdev_code_to_key(code);
+        if let Key::Unknown(_) = key {
+            unmapped.push(code);
+        }
+        let sample_ref = SampleRef::file(entry.path());
+        if pressed {
+            press_codes.push(code);
+            press.insert(key, sample_ref);
+        } else {
+            release_codes.push(code);
+            release.insert(key, sample_ref);
+        }
+    }
+
+    if press.is_empty() {
+        return Err(format!("样本目录 {} 中没有按下样本", dir.display()));
+    }
+
+    // 报告已知按键中缺少样本的键码
+    let format_codes = |codes: &[u16]| codes.iter().map(|code| format!("{:02x}", code)).collect::<Vec<_>>().join(" ");
+    let missing_press = missing_codes(&press_codes);
+    let missing_release = missing_codes(&release_codes);
+    if !missing_press.is_empty() {
+        warn!("样本目录 {} 缺少按下样本的键码: {}", name, format_codes(&missing_press));
+    }
+    if !missing_release.is_empty() {
+        warn!("样本目录 {} 缺少松开样本的键码: {}", name, format_codes(&missing_release));
+    }
+    if !unmapped.is_empty() {
+        unmapped.sort();
+        unmapped.dedup();
+        warn!("样本目录 {} 中无法识别的键码: {}", name, format_codes(&unmapped));
+    }
+
+    // 缺少样本的按键使用 A 键的按下样本
+    let fallback = press.get(&Key::KeyA).cloned();
+
+    info!("已加载 bucklespring 样本目录: {} ({} 个按下样本, {} 个松开样本)", name, press.len(), release.len());
+    Ok(SoundPack {
+        name,
+        dir: dir.to_path_buf(),
+        press,
+        release,
+        fallback,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pack_file_stays_inside_pack_dir() {
+        let dir = Path::new("/packs/cherry");
+        assert_eq!(pack_file(dir, "sound.ogg").unwrap(), dir.join("sound.ogg"));
+        assert_eq!(pack_file(dir, "keys/a.wav").unwrap(), dir.join("keys/a.wav"));
+        assert!(pack_file(dir, "../other/sound.ogg").is_err());
+        assert!(pack_file(dir, "keys/../../sound.ogg").is_err());
+        assert!(pack_file(dir, "/etc/passwd").is_err());
+        assert!(pack_file(dir, "").is_err());
+    }
+
+    // 在临时目录中创建音效包，files 为空文件（加载时只检查文件是否存在）
+    fn temp_pack(name: &str, config: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("macos-key-sound-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("config.json"), config).unwrap();
+        for file in files {
+            std::fs::write(dir.join(file), b"").unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn loads_single_file_pack_with_offsets() {
+        let dir = temp_pack("single", r#"{
+            "name": "Cherry",
+            "key_define_type": "single",
+            "sound": "sound.ogg",
+            "defines": { "30": [0, 100], "31": [120, 80], "57": null, "bad": [0, 1] }
+        }"#, &["sound.ogg"]);
+        let pack = load_mechvibes(&dir).unwrap();
+        let sprite = dir.join("sound.ogg");
+        assert_eq!(pack.name, "Cherry");
+        assert_eq!(pack.press.len(), 2);
+        assert_eq!(pack.press[&Key::KeyA], SampleRef::segment(sprite.clone(), 0, 100));
+        assert_eq!(pack.press[&Key::KeyS], SampleRef::segment(sprite.clone(), 120, 80));
+        assert_eq!(pack.fallback, Some(SampleRef::segment(sprite, 0, 100)));
+        assert!(pack.release.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn loads_multi_file_pack() {
+        let dir = temp_pack("multi", r#"{
+            "key_define_type": "multi",
+            "defines": { "31": "keys/s.wav", "57": "space.wav" }
+        }"#, &[]);
+        std::fs::create_dir_all(dir.join("keys")).unwrap();
+        std::fs::write(dir.join("keys/s.wav"), b"").unwrap();
+        std::fs::write(dir.join("space.wav"), b"").unwrap();
+        let pack = load_mechvibes(&dir).unwrap();
+        // 没有 name 时使用目录名
+        assert_eq!(pack.name, dir.file_name().unwrap().to_string_lossy());
+        assert_eq!(pack.press[&Key::KeyS], SampleRef::file(dir.join("keys/s.wav")));
+        assert_eq!(pack.press[&Key::Space], SampleRef::file(dir.join("space.wav")));
+        // 没有 A 键时使用扫描码最小的按键作为后备
+        assert_eq!(pack.fallback, Some(SampleRef::file(dir.join("keys/s.wav"))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bad_config_is_an_error() {
+        for (name, config) in [
+            ("not-json", "{ not json"),
+            ("no-defines", r#"{ "sound": "sound.ogg" }"#),
+            ("bad-type", r#"{ "key_define_type": "chord", "defines": {} }"#),
+            ("escape", r#"{ "sound": "../sound.ogg", "defines": { "30": [0, 100] } }"#),
+        ] {
+            let dir = temp_pack(name, config, &["sound.ogg"]);
+            assert!(load_mechvibes(&dir).is_err(), "{}", name);
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn missing_files_are_skipped_or_rejected() {
+        let dir = temp_pack("missing-multi", r#"{
+            "key_define_type": "multi",
+            "defines": { "30": "a.wav", "31": "gone.wav" }
+        }"#, &["a.wav"]);
+        let pack = load_mechvibes(&dir).unwrap();
+        assert!(pack.press.contains_key(&Key::KeyA));
+        assert!(!pack.press.contains_key(&Key::KeyS));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // 音频精灵不存在时整个音效包不可用
+        let dir = temp_pack("missing-sprite", r#"{
+            "sound": "gone.ogg",
+            "defines": { "30": [0, 100] }
+        }"#, &[]);
+        assert!(load_mechvibes(&dir).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // 所有文件都不存在时没有可用的按键
+        let dir = temp_pack("missing-all", r#"{
+            "key_define_type": "multi",
+            "defines": { "30": "gone.wav" }
+        }"#, &[]);
+        assert!(load_mechvibes(&dir).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parses_bucklespring_names() {
+        let cases = [
+            ("1e-1.wav", Some((0x1E, true))),
+            ("1e-0.wav", Some((0x1E, false))),
+            ("7D-1.wav", Some((0x7D, true))),
+            ("39-0.wav", Some((0x39, false))),
+            ("1e-2.wav", None),
+            ("1e-1.ogg", None),
+            ("1e1.wav", None),
+            ("e-1.wav", None),
+            ("1e0-1.wav", None),
+            ("zz-1.wav", None),
+            ("README.md", None),
+            ("", None),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(parse_bucklespring_name(name), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn reports_known_codes_without_samples() {
+        let all = missing_codes(&[]);
+        // evdev 键码 30 为 A 键，0 和未定义的键码不计入
+        assert!(all.contains(&30));
+        assert!(!all.contains(&0));
+        assert!(all.iter().all(|code| !matches!(evdev_code_to_key(*code), Key::Unknown(_))));
+
+        let missing = missing_codes(&[30, 31]);
+        assert_eq!(missing.len(), all.len() - 2);
+        assert!(!missing.contains(&30) && !missing.contains(&31));
+        assert!(missing_codes(&all).is_empty());
+    }
+
+    #[test]
+    fn loads_bucklespring_directory() {
+        let dir = temp_pack("bucklespring", "", &["1e-1.wav", "1e-0.wav", "1f-1.wav", "notes.txt"]);
+        std::fs::remove_file(dir.join("config.json")).unwrap();
+        assert!(is_bucklespring_pack(&dir));
+        let pack = load_bucklespring(&dir).unwrap();
+        assert_eq!(pack.press[&Key::KeyA], SampleRef::file(dir.join("1e-1.wav")));
+        assert_eq!(pack.release[&Key::KeyA], SampleRef::file(dir.join("1e-0.wav")));
+        assert_eq!(pack.press.len(), 2);
+        assert_eq!(pack.release.len(), 1);
+        assert_eq!(pack.fallback, Some(SampleRef::file(dir.join("1e-1.wav"))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn removed_files_fall_back_to_default_sample() {
+        let dir = PathBuf::from("/packs/cherry");
+        let good = SampleRef::file(dir.join("good.wav"));
+        let bad = SampleRef::file(dir.join("bad.wav"));
+        let mut pack = SoundPack {
+            name: "cherry".to_string(),
+            dir: dir.clone(),
+            press: HashMap::from([(Key::KeyA, bad.clone()), (Key::KeyB, good.clone())]),
+            release: HashMap::from([(Key::KeyA, bad.clone()), (Key::KeyC, bad.clone())]),
+            fallback: Some(good.clone()),
+        };
+
+        let affected = pack.remove_files(&[dir.join("bad.wav")]);
+        assert_eq!(affected, vec![Key::KeyA, Key::KeyC]);
+        assert_eq!(pack.press_sample(&Key::KeyA), Some(&good));
+        assert_eq!(pack.press_sample(&Key::KeyB), Some(&good));
+        assert_eq!(pack.release_sample(&Key::KeyA), None);
+        assert!(pack.files().iter().all(|file| file != &dir.join("bad.wav")));
+
+        pack.remove_files(&[dir.join("good.wav")]);
+        assert!(pack.is_empty());
+    }
+}
@@ -0,0 +1,210 @@
+// 程序化按键音合成器 - 没有任何音频文件时也能发声，按参数生成 click / clack / thock / 打字机声音
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::hash::{Hash, Hasher};
+
+use crate::audio_engine::{Sample, OUTPUT_CHANNELS, OUTPUT_SAMPLE_RATE};
+
+// 生成的样本最长时长，避免很长的衰减占用内存
+const MAX_DURATION_MS: f32 = 500.0;
+// 衰减到约 -50 dB 所需的时间约为 decay_ms 的 6 倍
+const TAIL_FACTOR: f32 = 6.0;
+// 起音时间，避免样本开头的突变产生额外的爆音
+const ATTACK_MS: f32 = 0.2;
+// 输出峰值
+const PEAK: f32 = 0.8;
+
+// 内置的合成音色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SynthPreset {
+    #[default]
+    Click,
+    Clack,
+    Thock,
+    Typewriter,
+}
+
+impl SynthPreset {
+    pub const ALL: [SynthPreset; 4] = [SynthPreset::Click, SynthPreset::Clack, SynthPreset::Thock, SynthPreset::Typewriter];
+
+    // 菜单中显示的音效名称
+    pub fn sound_name(self) -> &'static str {
+        match self {
+            SynthPreset::Click => "合成-click",
+            SynthPreset::Clack => "合成-clack",
+            SynthPreset::Thock => "合成-thock",
+            SynthPreset::Typewriter => "合成-typewriter",
+        }
+    }
+
+    fn voicing(self) -> SynthVoicing {
+        let (pitch, decay_ms, noise_mix, body_resonance) = match self {
+            SynthPreset::Click => (3000.0, 8.0, 0.7, 0.3),
+            SynthPreset::Clack => (1500.0, 18.0, 0.5, 0.5),
+            SynthPreset::Thock => (350.0, 35.0, 0.25, 0.7),
+            SynthPreset::Typewriter => (2200.0, 60.0, 0.4, 0.9),
+        };
+        SynthVoicing { pitch, decay_ms, noise_mix, body_resonance }
+    }
+}
+
+// 设置文件中的一个合成音效：选择音色，未填写的参数使用音色的默认值
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct SynthSettings {
+    pub preset: SynthPreset,
+    pub pitch: Option<f32>, // 键体共振频率，Hz
+    pub decay: Option<f32>, // 衰减时间常数，毫秒
+    pub noise_mix: Option<f32>, // 噪声（撞击声）所占比例 0.0 - 1.0
+    pub body_resonance: Option<f32>, // 键体共振强度 0.0 - 1.0，越大余音越长、音调越明显
+}
+
+impl SynthSettings {
+    pub fn voicing(&self) -> SynthVoicing {
+        let defaults = self.preset.voicing();
+        SynthVoicing {
+            pitch: self.pitch.unwrap_or(defaults.pitch).clamp(20.0, OUTPUT_SAMPLE_RATE as f32 / 2.5),
+            decay_ms: self.decay.unwrap_or(defaults.decay_ms).clamp(1.0, MAX_DURATION_MS),
+            noise_mix: self.noise_mix.unwrap_or(defaults.noise_mix).clamp(0.0, 1.0),
+            body_resonance: self.body_resonance.unwrap_or(defaults.body_resonance).clamp(0.0, 1.0),
+        }
+    }
+}
+
+// 内置音色加上设置文件中的合成音效（同名时设置文件优先），按名称排序
+pub fn synth_sounds(settings: &HashMap<String, SynthSettings>) -> Vec<(String, SynthVoicing)> {
+    let mut sounds: Vec<(String, SynthVoicing)> = SynthPreset::ALL.iter()
+        .filter(|preset| !settings.contains_key(preset.sound_name()))
+        .map(|preset| (preset.sound_name().to_string(), preset.voicing()))
+        .collect();
+    let mut custom: Vec<(String, SynthVoicing)> = settings.iter()
+        .map(|(name, synth)| (name.clone(), synth.voicing()))
+        .collect();
+    custom.sort_by(|a, b| a.0.cmp(&b.0));
+    sounds.extend(custom);
+    sounds
+}
+
+// 完整确定的合成参数，作为样本缓存的键
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SynthVoicing {
+    pub pitch: f32,
+    pub decay_ms: f32,
+    pub noise_mix: f32,
+    pub body_resonance: f32,
+}
+
+// 参数都经过 clamp，不会出现 NaN，可以按位比较
+impl Eq for SynthVoicing {}
+
+impl Hash for SynthVoicing {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.pitch.to_bits().hash(state);
+        self.decay_ms.to_bits().hash(state);
+        self.noise_mix.to_bits().hash(state);
+        self.body_resonance.to_bits().hash(state);
+    }
+}
+
+// 固定种子的 xorshift32 白噪声，同样的参数总是生成同样的样本
+struct Noise(u32);
+
+impl Noise {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+// RBJ 带通滤波器（0 dB 峰值增益），模拟键帽和外壳的共振
+struct Resonator {
+    b0: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl Resonator {
+    fn new(frequency: f32, q: f32) -> Self {
+        let w0 = 2.0 * PI * frequency / OUTPUT_SAMPLE_RATE as f32;
+        let alpha = w0.sin() / (2.0 * q);
+        let a0 = 1.0 + alpha;
+        Resonator {
+            b0: alpha / a0,
+            b2: -alpha / a0,
+            a1: -2.0 * w0.cos() / a0,
+            a2: (1.0 - alpha) / a0,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+        }
+    }
+
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.b0 * x + self.b2 * self.x2 - self.a1 * self.y1 - self.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+fn normalize(buffer: &mut [f32], peak: f32) {
+    let max = buffer.iter().fold(0.0f32, |max, value| max.max(value.abs()));
+    if max > 0.0 {
+        for value in buffer.iter_mut() {
+            *value *= peak / max;
+        }
+    }
+}
+
+// 合成一次按键声：一段快速衰减的噪声作为撞击声，同一段噪声激励共振滤波器作为键体声，两者按比例混合
+pub fn render(voicing: &SynthVoicing) -> Sample {
+    let rate = OUTPUT_SAMPLE_RATE as f32;
+    let duration_ms = (voicing.decay_ms * TAIL_FACTOR).min(MAX_DURATION_MS);
+    let frames = (duration_ms / 1000.0 * rate) as usize;
+    let decay = voicing.decay_ms / 1000.0 * rate;
+    let attack = ATTACK_MS / 1000.0 * rate;
+
+    let mut noise = Noise(0x2545_F491);
+    let q = 1.0 + voicing.body_resonance * 30.0;
+    let mut resonator = Resonator::new(voicing.pitch, q);
+
+    let mut strike = Vec::with_capacity(frames);
+    let mut body = Vec::with_capacity(frames);
+    for i in 0..frames {
+        let t = i as f32;
+        let attack_gain = (t / attack).min(1.0);
+        // 撞击声比键体声衰减快得多
+        let excitation = noise.next() * attack_gain * (-t / (decay * 0.25)).exp();
+        strike.push(excitation);
+        body.push(resonator.process(excitation) * (-t / decay).exp());
+    }
+    normalize(&mut strike, 1.0);
+    normalize(&mut body, 1.0);
+
+    let mut mono: Vec<f32> = strike.iter()
+        .zip(&body)
+        .map(|(strike, body)| strike * voicing.noise_mix + body * (1.0 - voicing.noise_mix))
+        .collect();
+    normalize(&mut mono, PEAK);
+
+    let mut data = Vec::with_capacity(frames * OUTPUT_CHANNELS as usize);
+    for value in mono {
+        for _ in 0..OUTPUT_CHANNELS {
+            data.push(value);
+        }
+    }
+    Sample { data }
+}
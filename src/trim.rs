@@ -0,0 +1,163 @@
+// 静音裁剪 - 找到样本的起音点，去掉开头和结尾的静音，减少按键到发声之间的延迟
+use serde::{Deserialize, Serialize};
+
+use std::time::Duration;
+
+use crate::audio_engine::{Sample, OUTPUT_CHANNELS, OUTPUT_SAMPLE_RATE};
+
+// 静音裁剪设置
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct TrimSettings {
+    pub enabled: bool,
+    pub threshold_db: f32, // 低于该峰值电平（dBFS）的部分视为静音
+    pub fade_ms: f32, // 裁剪处的淡入淡出时长，避免切口处产生爆音
+}
+
+impl Default for TrimSettings {
+    fn default() -> Self {
+        TrimSettings {
+            enabled: true,
+            threshold_db: -50.0,
+            fade_ms: 2.0,
+        }
+    }
+}
+
+// 一个样本被裁掉的开头和结尾时长
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Trimmed {
+    pub leading: Duration,
+    pub trailing: Duration,
+}
+
+impl Trimmed {
+    pub fn is_empty(&self) -> bool {
+        self.leading.is_zero() && self.trailing.is_zero()
+    }
+}
+
+fn frames_to_duration(frames: usize) -> Duration {
+    Duration::from_nanos(frames as u64 * 1_000_000_000 / OUTPUT_SAMPLE_RATE as u64)
+}
+
+// 裁剪开头和结尾的静音。起音点和最后一个超过门限的帧之外各保留 fade_ms 作为淡入淡出区，
+// 门限以上的部分保持原样；全部低于门限的样本不做处理
+pub fn trim(sample: &Sample, settings: &TrimSettings) -> (Sample, Trimmed) {
+    let channels = OUTPUT_CHANNELS as usize;
+    let threshold = 10f32.powf(settings.threshold_db / 20.0);
+    let loud = |frame: &[f32]| frame.iter().any(|value| value.abs() > threshold);
+
+    let frames: Vec<&[f32]> = sample.data.chunks(channels).collect();
+    let (onset, last) = match (frames.iter().position(|frame| loud(frame)), frames.iter().rposition(|frame| loud(frame))) {
+        (Some(onset), Some(last)) => (onset, last),
+        _ => return (Sample { data: sample.data.clone() }, Trimmed::default()),
+    };
+
+    let fade = (settings.fade_ms.max(0.0) / 1000.0 * OUTPUT_SAMPLE_RATE as f32) as usize;
+    let start = onset.saturating_sub(fade);
+    let end = (last + 1 + fade).min(frames.len());
+
+    let mut data = sample.data[start * channels..end * channels].to_vec();
+    let total = end - start;
+    // 只在真正裁剪过的一端淡入淡出，未裁剪的样本与原样本完全相同
+    if start > 0 {
+        let fade_in = onset - start;
+        for (i, frame) in data.chunks_mut(channels).take(fade_in).enumerate() {
+            let gain = i as f32 / fade_in as f32;
+            frame.iter_mut().for_each(|value| *value *= gain);
+        }
+    }
+    if end < frames.len() {
+        let fade_out = end - (last + 1);
+        for (i, frame) in data.chunks_mut(channels).skip(total - fade_out).enumerate() {
+            let gain = 1.0 - (i + 1) as f32 / fade_out as f32;
+            frame.iter_mut().for_each(|value| *value *= gain);
+        }
+    }
+
+    let trimmed = Trimmed {
+        leading: frames_to_duration(start),
+        trailing: frames_to_duration(frames.len() - end),
+    };
+    (Sample { data }, trimmed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANNELS: usize = OUTPUT_CHANNELS as usize;
+    // 默认 2 ms 淡入淡出区的帧数
+    const FADE: usize = 88;
+
+    // 依次拼接若干段 (帧数, 左声道值, 右声道值)
+    fn sample(parts: &[(usize, f32, f32)]) -> Sample {
+        Sample { data: parts.iter().flat_map(|&(frames, left, right)| (0..frames).flat_map(move |_| [left, right])).collect() }
+    }
+
+    #[test]
+    fn trims_leading_and_trailing_silence() {
+        // 静音段里是低于 -50 dB 门限的底噪
+        let original = sample(&[(1000, 0.001, -0.001), (500, 0.5, -0.5), (2000, 0.001, 0.001)]);
+        let (trimmed, report) = trim(&original, &TrimSettings::default());
+
+        assert_eq!(report.leading, frames_to_duration(1000 - FADE));
+        assert_eq!(report.trailing, frames_to_duration(2000 - FADE));
+        assert_eq!(trimmed.frames(), 500 + 2 * FADE);
+        // 门限以上的部分保持原样
+        assert_eq!(&trimmed.data[FADE * CHANNELS..(FADE + 500) * CHANNELS], &original.data[1000 * CHANNELS..1500 * CHANNELS]);
+    }
+
+    #[test]
+    fn keeps_attack_pre_roll_as_fade_in() {
+        let original = sample(&[(1000, 0.002, 0.002), (500, 0.5, 0.5)]);
+        let (trimmed, report) = trim(&original, &TrimSettings::default());
+
+        assert_eq!(report.leading, frames_to_duration(1000 - FADE));
+        assert!(report.trailing.is_zero());
+        // 起音前保留 FADE 帧，从 0 逐渐淡入，不会把起音本身切掉
+        let pre_roll: Vec<f32> = trimmed.data.chunks(CHANNELS).take(FADE).map(|frame| frame[0]).collect();
+        assert_eq!(pre_roll[0], 0.0);
+        assert!(pre_roll.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(pre_roll.iter().all(|value| *value < 0.002));
+        assert_eq!(trimmed.data[FADE * CHANNELS], 0.5);
+    }
+
+    #[test]
+    fn all_silent_sample_is_left_alone() {
+        for original in [sample(&[(4410, 0.0, 0.0)]), sample(&[(4410, 0.001, -0.001)]), sample(&[])] {
+            let (trimmed, report) = trim(&original, &TrimSettings::default());
+            assert_eq!(trimmed.data, original.data);
+            assert!(report.is_empty());
+        }
+    }
+
+    #[test]
+    fn keeps_frames_aligned_across_channels() {
+        // 只有右声道有声音时也按整帧裁剪，左右声道不会错位
+        let original = sample(&[(1001, 0.0, 0.0), (10, 0.0, 0.5), (999, 0.0, 0.0)]);
+        let (trimmed, report) = trim(&original, &TrimSettings::default());
+
+        assert_eq!(trimmed.data.len() % CHANNELS, 0);
+        assert_eq!(report.leading, frames_to_duration(1001 - FADE));
+        let onset = &trimmed.data[FADE * CHANNELS..(FADE + 1) * CHANNELS];
+        assert_eq!(onset, &[0.0, 0.5]);
+    }
+
+    #[test]
+    fn untrimmed_ends_are_unchanged() {
+        // 开头就有声音、结尾离最后一个响亮帧不到淡出区长度时，两端都不动
+        let original = sample(&[(100, 0.5, 0.5), (FADE / 2, 0.0, 0.0)]);
+        let (trimmed, report) = trim(&original, &TrimSettings::default());
+        assert_eq!(trimmed.data, original.data);
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn zero_fade_cuts_at_threshold() {
+        let original = sample(&[(1000, 0.0, 0.0), (500, 0.5, 0.5), (1000, 0.0, 0.0)]);
+        let (trimmed, _) = trim(&original, &TrimSettings { fade_ms: 0.0, ..TrimSettings::default() });
+        assert_eq!(trimmed.data, sample(&[(500, 0.5, 0.5)]).data);
+    }
+}
@@ -0,0 +1,151 @@
+// 音效变化 - 随机变调、音量抖动和多样本轮换，避免同一个样本机械地重复
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::audio_engine::VoiceParams;
+
+// 一个音效的变化设置，全部为 0 / 空时与原样播放一致
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Variation {
+    pub pitch_cents: f32, // 随机变调范围，±音分（100 音分为一个半音）
+    pub gain_jitter_db: f32, // 随机音量范围，±分贝
+    pub pool: Vec<String>, // 与该音效轮流使用的其他声音文件名
+}
+
+// splitmix64：状态只有一个 u64，给定种子时输出序列固定，便于测试和离线渲染复现
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // [-1, 1) 区间的均匀分布
+    fn next_signed(&mut self) -> f32 {
+        let unit = (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32;
+        unit * 2.0 - 1.0
+    }
+}
+
+// 变化状态：随机数发生器和每个音效的轮换位置
+pub struct Varier {
+    rng: Mutex<SplitMix64>,
+    round_robin: Mutex<HashMap<String, usize>>,
+}
+
+impl Varier {
+    pub fn new(seed: u64) -> Self {
+        Varier {
+            rng: Mutex::new(SplitMix64(seed)),
+            round_robin: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // 按 音效本身 -> pool 中的各个音效 的顺序轮流选择。设置修改后 pool 可能变短，
+    // 轮换位置超出时从音效本身重新开始
+    pub fn pick<'a>(&self, sound_name: &'a str, variation: &'a Variation) -> &'a str {
+        let mut round_robin = self.round_robin.lock().unwrap();
+        let index = round_robin.entry(sound_name.to_string()).or_insert(0);
+        let picked = match *index {
+            0 => None,
+            n => variation.pool.get(n - 1),
+        };
+        let Some(picked) = picked else {
+            *index = 1 % (variation.pool.len() + 1);
+            return sound_name;
+        };
+        *index = (*index + 1) % (variation.pool.len() + 1);
+        picked
+    }
+
+    // 在播放参数上叠加随机变调和音量抖动
+    pub fn apply(&self, variation: &Variation, params: VoiceParams) -> VoiceParams {
+        if variation.pitch_cents == 0.0 && variation.gain_jitter_db == 0.0 {
+            return params;
+        }
+        let mut rng = self.rng.lock().unwrap();
+        let cents = rng.next_signed() * variation.pitch_cents;
+        let decibels = rng.next_signed() * variation.gain_jitter_db;
+        let mut varied = params;
+        varied.gain *= 10f32.powf(decibels / 20.0);
+        varied.rate *= 2f32.powf(cents / 1200.0);
+        varied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variation(pool: &[&str]) -> Variation {
+        Variation {
+            pitch_cents: 100.0,
+            gain_jitter_db: 6.0,
+            pool: pool.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    fn varied(seed: u64, count: usize) -> Vec<(f32, f32)> {
+        let varier = Varier::new(seed);
+        let variation = variation(&[]);
+        (0..count)
+            .map(|_| varier.apply(&variation, VoiceParams::new(1.0)))
+            .map(|params| (params.gain, params.rate))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_variation() {
+        assert_eq!(varied(42, 16), varied(42, 16));
+    }
+
+    #[test]
+    fn different_seeds_diverge() {
+        assert_ne!(varied(1, 16), varied(2, 16));
+    }
+
+    #[test]
+    fn variation_stays_in_range() {
+        for (gain, rate) in varied(7, 256) {
+            assert!((0.5..=2.0).contains(&gain), "gain {}", gain);
+            assert!((0.5f32.sqrt()..=2f32.sqrt()).contains(&rate), "rate {}", rate);
+        }
+    }
+
+    #[test]
+    fn zero_variation_leaves_params_untouched() {
+        let varier = Varier::new(3);
+        let params = varier.apply(&Variation::default(), VoiceParams::new(0.8));
+        assert_eq!((params.gain, params.rate), (0.8, 1.0));
+    }
+
+    #[test]
+    fn pick_rotates_through_pool() {
+        let varier = Varier::new(0);
+        let variation = variation(&["b", "c"]);
+        let picks: Vec<_> = (0..6).map(|_| varier.pick("a", &variation)).collect();
+        assert_eq!(picks, vec!["a", "b", "c", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn pick_survives_shrunken_pool() {
+        let varier = Varier::new(0);
+        let long = variation(&["b", "c"]);
+        assert_eq!(varier.pick("a", &long), "a");
+        assert_eq!(varier.pick("a", &long), "b");
+        // 下一次本应选 pool[1]，pool 已缩短为空
+        let empty = variation(&[]);
+        assert_eq!(varier.pick("a", &empty), "a");
+        assert_eq!(varier.pick("a", &empty), "a");
+        let short = variation(&["b"]);
+        assert_eq!(varier.pick("a", &short), "a");
+        assert_eq!(varier.pick("a", &short), "b");
+    }
+}